@@ -0,0 +1,60 @@
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Error reported by a guest. `code` is meant to be matched on, `message` is for people and
+/// `details`, if present, is a JSON document.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GuestError {
+	pub code: String,
+	pub message: String,
+	#[serde(default)]
+	pub details: Option<Value>,
+	#[serde(default)]
+	pub retryable: bool,
+	#[serde(default)]
+	pub origin: ErrorOrigin,
+}
+
+/// Who raised a [`GuestError`]. Only the executor raises errors that stand for something other
+/// than a decision of the state, such as running out of update iterations, so the host trusts
+/// those codes only with this origin.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum ErrorOrigin {
+	#[default]
+	State,
+	Executor,
+}
+
+impl GuestError {
+	pub fn new<C: Into<String>, M: Into<String>>(code: C, message: M) -> GuestError {
+		GuestError {
+			code: code.into(),
+			message: message.into(),
+			details: None,
+			retryable: false,
+			origin: ErrorOrigin::State,
+		}
+	}
+
+	/// An error raised by the executor rather than by the state.
+	pub fn executor<C: Into<String>, M: Into<String>>(code: C, message: M) -> GuestError {
+		GuestError {
+			origin: ErrorOrigin::Executor,
+			..GuestError::new(code, message)
+		}
+	}
+
+	pub fn is_executor(&self, code: &str) -> bool {
+		self.origin == ErrorOrigin::Executor && self.code == code
+	}
+}
+
+impl Display for GuestError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}: {}", self.code, self.message)
+	}
+}
+
+impl std::error::Error for GuestError {}
@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+/// Whether a state accepted an event. A dropped event leaves the state as it was.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum EventStatus {
+	#[default]
+	Consumed,
+	Dropped,
+}
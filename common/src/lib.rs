@@ -1,7 +1,13 @@
+pub use completion::Completion;
+pub use error::{ErrorOrigin, GuestError};
+pub use event_status::EventStatus;
 pub use operation::Operation;
 pub use request::Request;
-pub use response::{Response, Snapshot};
+pub use response::{Response, Schemas, Snapshot};
 
+mod completion;
+mod error;
+mod event_status;
 mod operation;
 mod request;
 mod response;
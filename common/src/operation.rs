@@ -1,8 +1,10 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug)]
+use crate::GuestError;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Operation {
 	Event(String),
 	Info(String),
-	Error(String),
+	Error(GuestError),
 }
@@ -2,6 +2,28 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Request {
-	Initialization { parameter: String },
-	Event { state: String, event: String },
+	Initialization {
+		parameter: String,
+	},
+	Event {
+		state: String,
+		event: String,
+		/// Turns a dropped event into an `event_dropped` error explaining why every store
+		/// rejected it.
+		#[serde(default)]
+		diagnose: bool,
+	},
+	/// Applies `events` in order within one execution. If any of them fails, the whole batch
+	/// fails.
+	Events {
+		state: String,
+		events: Vec<String>,
+	},
+	/// Computes a view of `state` without changing it.
+	Query {
+		state: String,
+		query: String,
+	},
+	/// Asks for the JSON Schemas of the module's parameter, events and state.
+	Describe,
 }
@@ -1,14 +1,38 @@
-use crate::Operation;
+use std::collections::BTreeMap;
+
+use crate::{Completion, EventStatus, GuestError, Operation};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Response {
-	Error(String),
+	Error(GuestError),
 	Snapshot(Snapshot),
+	Schemas(Schemas),
+	/// JSON result of a `Request::Query`.
+	QueryResult(String),
+}
+
+/// JSON Schemas of a module, each one a JSON document.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Schemas {
+	pub parameter: String,
+	pub event: String,
+	pub state: String,
+	pub query: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Snapshot {
 	pub operations: Vec<Operation>,
 	pub state: String,
+	#[serde(default)]
+	pub completion: Option<Completion>,
+	#[serde(default)]
+	pub indexes: BTreeMap<String, String>,
+	/// Always `Consumed` for an initialization. For a batch, `Consumed` if any event was.
+	#[serde(default)]
+	pub event_status: EventStatus,
+	/// Status of each event of a `Request::Events`, in order.
+	#[serde(default)]
+	pub event_statuses: Vec<EventStatus>,
 }
@@ -1,6 +1,7 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use guest::{Actions, BaseStore, EventStatus, Executor, GuestInterface, State};
+use guest::{Actions, BaseStore, Error, EventStatus, Executor, GuestInterface, State};
 
 #[no_mangle]
 extern "C" fn alloc(size: i32) -> i32 {
@@ -23,26 +24,33 @@ impl Executor for WorkflowExecutor {
 	type RootState = WorkflowState;
 }
 
-#[derive(Serialize, Deserialize, Eq, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Eq, PartialEq, Clone)]
 struct WorkflowState {
 	accumulator: isize,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 enum WorkflowStateEvent {
 	Add(isize),
 	Subtract(isize),
 	Multiply(isize),
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 struct WorkflowStateParameter {
 	initial: isize,
 }
 
+#[derive(Deserialize, JsonSchema)]
+enum WorkflowStateQuery {
+	Accumulator,
+	IsNegative,
+}
+
 impl State for WorkflowState {
 	type Event = WorkflowStateEvent;
 	type Parameter = WorkflowStateParameter;
+	type Query = WorkflowStateQuery;
 
 	fn entry(parameter: Self::Parameter) -> (Self, Actions) {
 		(
@@ -57,7 +65,7 @@ impl State for WorkflowState {
 		match event {
 			WorkflowStateEvent::Add(n) => (
 				WorkflowState {
-					accumulator: self.accumulator + n.clone(),
+					accumulator: self.accumulator + n,
 				},
 				EventStatus::Consumed,
 				Actions::new(),
@@ -79,6 +87,13 @@ impl State for WorkflowState {
 		}
 	}
 
+	fn query(&self, query: Self::Query) -> Result<serde_json::Value, Error> {
+		Ok(match query {
+			WorkflowStateQuery::Accumulator => self.accumulator.into(),
+			WorkflowStateQuery::IsNegative => (self.accumulator < 0).into(),
+		})
+	}
+
 	fn update(self) -> (Self, Actions) {
 		(self, Actions::new())
 	}
@@ -1,13 +1,18 @@
-use serde::Serialize;
-
 use common::Operation;
 
+use crate::error::Error;
 use crate::event::OutgoingEvent;
 
 pub struct Actions {
 	logs: Vec<ActionLog>,
 }
 
+impl Default for Actions {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl Actions {
 	pub fn new() -> Actions {
 		Actions { logs: Vec::new() }
@@ -23,6 +28,12 @@ impl Actions {
 		self
 	}
 
+	/// Reports `error` to the host without rejecting the event.
+	pub fn error<T: Into<Error>>(mut self, error: T) -> Self {
+		self.logs.push(ActionLog::Error(error.into()));
+		self
+	}
+
 	pub fn merge(mut self, mut other: Actions) -> Self {
 		self.logs.append(&mut other.logs);
 		self
@@ -34,6 +45,7 @@ impl Actions {
 			operations.push(match action_log {
 				ActionLog::Event(e) => Operation::Event(e.get_raw()?),
 				ActionLog::Info(s) => Operation::Info(s),
+				ActionLog::Error(e) => Operation::Error(e.build()?),
 			});
 		}
 		Ok(operations)
@@ -43,4 +55,5 @@ impl Actions {
 enum ActionLog {
 	Event(Box<dyn OutgoingEvent>),
 	Info(String),
+	Error(Error),
 }
@@ -0,0 +1,9 @@
+use serde::Serialize;
+
+/// Why one store did not consume an event. `error` is `None` if the event parsed but the state
+/// dropped it.
+#[derive(Serialize, Debug)]
+pub struct Diagnostic {
+	pub state: &'static str,
+	pub error: Option<String>,
+}
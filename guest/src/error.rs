@@ -0,0 +1,60 @@
+use common::{ErrorOrigin, GuestError};
+
+use crate::event::OutgoingEvent;
+
+/// Typed error a [`State`](crate::State) reports to the host. Implement `From<YourError>` for
+/// it to report your own error types.
+pub struct Error {
+	code: String,
+	message: String,
+	details: Option<Box<dyn OutgoingEvent>>,
+	retryable: bool,
+}
+
+impl Error {
+	pub fn new<C: Into<String>, M: Into<String>>(code: C, message: M) -> Self {
+		Error {
+			code: code.into(),
+			message: message.into(),
+			details: None,
+			retryable: false,
+		}
+	}
+
+	pub fn details<T: OutgoingEvent + 'static>(mut self, details: T) -> Self {
+		self.details = Some(Box::new(details));
+		self
+	}
+
+	/// Marks the error as transient: sending the same event again may succeed.
+	pub fn retryable(mut self) -> Self {
+		self.retryable = true;
+		self
+	}
+
+	pub fn code(&self) -> &str {
+		self.code.as_str()
+	}
+
+	/// Builds the error and wraps it for the executor, which reports it as it is.
+	pub(crate) fn into_anyhow(self) -> anyhow::Error {
+		match self.build() {
+			Ok(error) => error.into(),
+			Err(error) => error,
+		}
+	}
+
+	pub fn build(self) -> anyhow::Result<GuestError> {
+		let details = match self.details {
+			Some(details) => Some(serde_json::from_str(details.get_raw()?.as_str())?),
+			None => None,
+		};
+		Ok(GuestError {
+			code: self.code,
+			message: self.message,
+			details,
+			retryable: self.retryable,
+			origin: ErrorOrigin::State,
+		})
+	}
+}
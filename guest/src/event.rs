@@ -1,4 +1,4 @@
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 
 pub trait OutgoingEvent {
 	fn get_raw(&self) -> anyhow::Result<String>;
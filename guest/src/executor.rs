@@ -1,7 +1,8 @@
-use common::{Request, Response, Snapshot};
+use common::{GuestError, Request, Response, Schemas, Snapshot};
 
-use crate::actions;
 use crate::actions::Actions;
+use crate::error::Error;
+use crate::outcome::Outcome;
 use crate::state::{EventStatus, State};
 use crate::store::{BaseStore, StateStatus, Store};
 
@@ -16,54 +17,155 @@ pub trait Executor {
 		}
 	}
 
+	/// Replies to a request that does not parse, such as one this module is too old to know,
+	/// with an `invalid_request` error.
 	fn raw_execute(input: &str) -> anyhow::Result<String> {
-		let request = serde_json::from_str(input)?;
-		let response = match Self::serialized_execute(request) {
-			Ok(snapshot) => serde_json::to_string(&Response::Snapshot(snapshot))?,
-			Err(error) => serde_json::to_string(&Response::Error(error.to_string()))?,
+		let request = serde_json::from_str(input).map_err(anyhow::Error::from);
+		let response = match request.and_then(Self::serialized_execute) {
+			Ok(response) => serde_json::to_string(&response)?,
+			Err(error) => serde_json::to_string(&Response::Error(guest_error(error)))?,
 		};
 		Ok(response)
 	}
 
-	fn serialized_execute(request: Request) -> anyhow::Result<Snapshot> {
+	fn serialized_execute(request: Request) -> anyhow::Result<Response> {
 		let snapshot = match request {
 			Request::Initialization { parameter } => Self::execute_initialization(parameter)?,
-			Request::Event { state, event } => Self::execute_event(state, event)?,
+			Request::Event {
+				state,
+				event,
+				diagnose,
+			} => {
+				let snapshot = Self::execute_event(state.clone(), event.clone())?;
+				if diagnose && snapshot.event_status == EventStatus::Dropped {
+					return Err(Self::diagnose_event(state, event)?.into());
+				}
+				snapshot
+			}
+			Request::Events { state, events } => Self::execute_events(state, events)?,
+			Request::Query { state, query } => {
+				return Ok(Response::QueryResult(Self::execute_query(state, query)?))
+			}
+			Request::Describe => {
+				let schemas = Self::describe()
+					.map_err(|error| GuestError::executor("describe_failed", error.to_string()))?;
+				return Ok(Response::Schemas(schemas));
+			}
 		};
-		Ok(snapshot)
+		Ok(Response::Snapshot(snapshot))
+	}
+
+	fn execute_query(state: String, query: String) -> anyhow::Result<String> {
+		let state: Self::RootState = serde_json::from_str(state.as_str())?;
+		let query = serde_json::from_str(query.as_str())
+			.map_err(|error| GuestError::executor("invalid_query", error.to_string()))?;
+		let result = state.query(query).map_err(Error::into_anyhow)?;
+		Ok(serde_json::to_string(&result)?)
+	}
+
+	fn describe() -> anyhow::Result<Schemas> {
+		crate::schema::describe::<Self::RootState>()
 	}
 
 	fn execute_initialization(parameter: String) -> anyhow::Result<Snapshot> {
 		let parameter = serde_json::from_str(parameter.as_str())?;
-		let (state, actions) = Self::RootState::entry(parameter);
-		let snapshot = Snapshot {
-			operations: actions.build()?,
-			state: serde_json::to_string(&state)?,
-		};
-		Ok(snapshot)
+		let (state, actions) = Self::RootState::try_entry(parameter).map_err(Error::into_anyhow)?;
+		Self::snapshot(Store::new(state), actions, EventStatus::Consumed)
 	}
 
 	fn execute_event(state: String, event: String) -> anyhow::Result<Snapshot> {
 		let mut store: Store<Self::RootState> = Store::new(serde_json::from_str(state.as_str())?);
-		let (mut event_status, mut actions) = store.process(event.as_str());
-		if event_status == EventStatus::Consumed {
-			for _ in 0..Self::UPDATE_LIMIT {
-				let (state_status, new_actions) = store.update();
-				actions = actions.merge(new_actions);
-				if state_status == StateStatus::Same {
-					return Self::snapshot(store, actions);
-				}
+		let (event_status, actions) = Self::apply_event(&mut store, event.as_str())?;
+		Self::snapshot(store, actions, event_status)
+	}
+
+	/// Applies `events` in order to one state and combines their operations. The message of an
+	/// error names the event that caused it. A process that reaches an outcome accepts no more
+	/// events, so a batch going on past that event fails with `process_completed`.
+	fn execute_events(state: String, events: Vec<String>) -> anyhow::Result<Snapshot> {
+		let mut store: Store<Self::RootState> = Store::new(serde_json::from_str(state.as_str())?);
+		let mut actions = Actions::new();
+		let mut event_statuses = Vec::with_capacity(events.len());
+		for (index, event) in events.iter().enumerate() {
+			let (event_status, new_actions) = Self::apply_event(&mut store, event.as_str())
+				.map_err(|error| {
+					let mut error = guest_error(error);
+					error.message = format!("event {}: {}", index, error.message);
+					error
+				})?;
+			actions = actions.merge(new_actions);
+			event_statuses.push(event_status);
+			if store.outcome().is_some() && index + 1 < events.len() {
+				return Err(GuestError::executor(
+					"process_completed",
+					format!("event {}: process completed at event {}", index + 1, index),
+				)
+				.into());
+			}
+		}
+		let event_status = match event_statuses.contains(&EventStatus::Consumed) {
+			true => EventStatus::Consumed,
+			false => EventStatus::Dropped,
+		};
+		let mut snapshot = Self::snapshot(store, actions, event_status)?;
+		snapshot.event_statuses = event_statuses;
+		Ok(snapshot)
+	}
+
+	fn apply_event(
+		store: &mut Store<Self::RootState>,
+		event: &str,
+	) -> anyhow::Result<(EventStatus, Actions)> {
+		let (event_status, mut actions) = store.process(event)?;
+		if event_status == EventStatus::Dropped {
+			return Ok((event_status, actions));
+		}
+		for _ in 0..Self::UPDATE_LIMIT {
+			let (state_status, new_actions) = store.update();
+			actions = actions.merge(new_actions);
+			if state_status == StateStatus::Same {
+				return Ok((event_status, actions));
 			}
-			return Err(anyhow::Error::msg("update_limit_exceeded"));
 		}
-		return Self::snapshot(store, actions);
+		Err(GuestError::executor(
+			"update_limit_exceeded",
+			format!("state still changing after {} updates", Self::UPDATE_LIMIT),
+		)
+		.into())
 	}
 
-	fn snapshot(store: Store<Self::RootState>, actions: Actions) -> anyhow::Result<Snapshot> {
+	/// Collects why each store of `state`, outermost last, did not consume `event`.
+	fn diagnose_event(state: String, event: String) -> anyhow::Result<GuestError> {
+		let mut store: Store<Self::RootState> = Store::new(serde_json::from_str(state.as_str())?);
+		let mut diagnostics = Vec::new();
+		store.diagnose(event.as_str(), &mut diagnostics);
+		let mut error = GuestError::executor("event_dropped", "no state accepted the event");
+		error.details = Some(serde_json::to_value(&diagnostics)?);
+		Ok(error)
+	}
+
+	fn snapshot(
+		store: Store<Self::RootState>,
+		actions: Actions,
+		event_status: EventStatus,
+	) -> anyhow::Result<Snapshot> {
 		let snapshot = Snapshot {
 			operations: actions.build()?,
 			state: serde_json::to_string(&store)?,
+			completion: store.outcome().map(Outcome::build).transpose()?,
+			indexes: store.indexes(),
+			event_status,
+			event_statuses: Vec::new(),
 		};
-		return Ok(snapshot);
+		Ok(snapshot)
+	}
+}
+
+/// Passes typed errors through and reports anything else, such as input that does not
+/// deserialize, as `invalid_request`.
+fn guest_error(error: anyhow::Error) -> GuestError {
+	match error.downcast::<GuestError>() {
+		Ok(error) => error,
+		Err(error) => GuestError::executor("invalid_request", error.to_string()),
 	}
 }
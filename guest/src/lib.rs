@@ -1,29 +1,40 @@
-use std::alloc::Layout;
-
 pub use actions::Actions;
+pub use diagnostic::Diagnostic;
+pub use error::Error;
 pub use executor::Executor;
 pub use guest_interface::GuestInterface;
+pub use outcome::Outcome;
 pub use state::{EventStatus, State};
 pub use store::{BaseStore, Store};
 
 mod actions;
+mod diagnostic;
+mod error;
 mod event;
 mod executor;
 mod guest_interface;
+mod outcome;
+mod schema;
 mod state;
 mod store;
 
 #[cfg(test)]
 mod tests {
+	use std::collections::BTreeMap;
+
+	use schemars::JsonSchema;
 	use serde::{Deserialize, Serialize};
 
+	use common::{Completion, ErrorOrigin, GuestError, Operation, Request, Response};
+
 	use crate::actions::Actions;
+	use crate::error::Error;
 	use crate::executor::Executor;
-	use crate::state;
+	use crate::outcome::Outcome;
 	use crate::state::{EventStatus, State};
 	use crate::store::{BaseStore, Store};
 
-	#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+	#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema, Debug)]
 	enum SuperState {
 		None(String),
 		Single(String, Store<MyState>),
@@ -33,8 +44,9 @@ mod tests {
 	impl State for SuperState {
 		type Event = String;
 		type Parameter = ();
+		type Query = ();
 
-		fn entry(parameter: Self::Parameter) -> (Self, Actions) {
+		fn entry(_parameter: Self::Parameter) -> (Self, Actions) {
 			(SuperState::None("s".to_string()), Actions::new())
 		}
 
@@ -52,7 +64,7 @@ mod tests {
 					EventStatus::Consumed,
 					Actions::new(),
 				),
-				SuperState::Double(s, m1, m2) => (
+				SuperState::Double(s, _, _) => (
 					SuperState::None(s + event.as_str()),
 					EventStatus::Consumed,
 					Actions::new(),
@@ -75,7 +87,7 @@ mod tests {
 		}
 	}
 
-	#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
+	#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema, Debug)]
 	enum MyState {
 		A(i32),
 		B(i32),
@@ -86,8 +98,9 @@ mod tests {
 	impl State for MyState {
 		type Event = i32;
 		type Parameter = ();
+		type Query = ();
 
-		fn entry(parameter: Self::Parameter) -> (Self, Actions) {
+		fn entry(_parameter: Self::Parameter) -> (Self, Actions) {
 			(MyState::A(0), Actions::new())
 		}
 
@@ -144,4 +157,307 @@ mod tests {
 			println!("state: {}\n", state);
 		}
 	}
+
+	#[derive(Clone, Eq, PartialEq, Serialize, Deserialize, JsonSchema, Debug)]
+	struct CountdownState(i32);
+
+	impl State for CountdownState {
+		type Event = i32;
+		type Parameter = i32;
+		type Query = CountdownQuery;
+
+		fn entry(parameter: Self::Parameter) -> (Self, Actions) {
+			(CountdownState(parameter), Actions::new())
+		}
+
+		fn process(self, event: Self::Event) -> (Self, EventStatus, Actions) {
+			let actions = match event {
+				0 => Actions::new().error(
+					Error::new("empty_step", "counting down by 0")
+						.details(self.0)
+						.retryable(),
+				),
+				_ => Actions::new(),
+			};
+			(
+				CountdownState(self.0 - event),
+				EventStatus::Consumed,
+				actions,
+			)
+		}
+
+		fn try_entry(parameter: Self::Parameter) -> Result<(Self, Actions), Error> {
+			match parameter {
+				n if n < 0 => Err(Error::new(
+					"negative_start",
+					"cannot count down from below 0",
+				)),
+				n => Ok(Self::entry(n)),
+			}
+		}
+
+		fn try_process(self, event: Self::Event) -> Result<(Self, EventStatus, Actions), Error> {
+			match event {
+				n if n < 0 => Err(Error::new("negative_step", "cannot count up").details(n)),
+				n => Ok(self.process(n)),
+			}
+		}
+
+		fn update(self) -> (Self, Actions) {
+			(self, Actions::new())
+		}
+
+		fn inner_store(&mut self) -> Vec<&mut dyn BaseStore> {
+			vec![]
+		}
+
+		fn outcome(&self) -> Option<Outcome> {
+			match self.0 {
+				0 => Some(Outcome::completed("done")),
+				n if n < 0 => Some(Outcome::failed(n)),
+				_ => None,
+			}
+		}
+
+		fn query(&self, query: Self::Query) -> Result<serde_json::Value, Error> {
+			match query {
+				CountdownQuery::Remaining => Ok(self.0.into()),
+				CountdownQuery::StepsOf(n) if n > 0 => Ok(((self.0 + n - 1) / n).into()),
+				CountdownQuery::StepsOf(_) => {
+					Err(Error::new("invalid_step", "steps must be positive"))
+				}
+			}
+		}
+
+		fn indexes(&self) -> BTreeMap<String, String> {
+			BTreeMap::from([("remaining".to_string(), self.0.to_string())])
+		}
+	}
+
+	#[derive(Deserialize, JsonSchema)]
+	enum CountdownQuery {
+		Remaining,
+		StepsOf(i32),
+	}
+
+	struct CountdownExecutor;
+	impl Executor for CountdownExecutor {
+		type RootState = CountdownState;
+	}
+
+	#[test]
+	fn test_outcome() {
+		let snapshot = CountdownExecutor::execute_initialization("2".to_string()).unwrap();
+		assert_eq!(snapshot.completion, None);
+		assert_eq!(snapshot.indexes["remaining"], "2");
+		let snapshot =
+			CountdownExecutor::execute_event(snapshot.state, "\"x\"".to_string()).unwrap();
+		assert_eq!(snapshot.event_status, EventStatus::Dropped);
+		assert_eq!(snapshot.state, "2");
+		let snapshot = CountdownExecutor::execute_event(snapshot.state, "2".to_string()).unwrap();
+		assert_eq!(
+			snapshot.completion,
+			Some(Completion::Completed("\"done\"".to_string()))
+		);
+		let snapshot = CountdownExecutor::execute_event(snapshot.state, "1".to_string()).unwrap();
+		assert_eq!(
+			snapshot.completion,
+			Some(Completion::Failed("-1".to_string()))
+		);
+	}
+
+	#[test]
+	fn test_errors() {
+		let snapshot = CountdownExecutor::execute_initialization("2".to_string()).unwrap();
+		let snapshot = CountdownExecutor::execute_event(snapshot.state, "0".to_string()).unwrap();
+		let Operation::Error(error) = &snapshot.operations[0] else {
+			panic!("expected an error operation");
+		};
+		assert_eq!(error.code, "empty_step");
+		assert_eq!(error.details, Some(serde_json::json!(2)));
+		assert!(error.retryable);
+
+		let request = "{\"Event\":{\"state\":\"[]\",\"event\":\"1\"}}";
+		let response = CountdownExecutor::execute(request);
+		let Response::Error(error) = serde_json::from_str(response.as_str()).unwrap() else {
+			panic!("expected an error response");
+		};
+		assert_eq!(error.code, "invalid_request");
+		assert_eq!(error.origin, ErrorOrigin::Executor);
+		assert!(!error.retryable);
+		let response = CountdownExecutor::execute("\"Unknown\"");
+		let Response::Error(error) = serde_json::from_str(response.as_str()).unwrap() else {
+			panic!("expected an error response");
+		};
+		assert_eq!(error.code, "invalid_request");
+		assert_eq!(
+			serde_json::from_str::<GuestError>("{\"code\":\"a\",\"message\":\"b\"}").unwrap(),
+			GuestError::new("a", "b")
+		);
+	}
+
+	#[test]
+	fn test_fallible_transitions() {
+		let error = CountdownExecutor::execute_initialization("-1".to_string()).unwrap_err();
+		assert_eq!(
+			error.downcast::<GuestError>().unwrap().code,
+			"negative_start"
+		);
+
+		let snapshot = CountdownExecutor::execute_initialization("2".to_string()).unwrap();
+		let request = Request::Event {
+			state: snapshot.state,
+			event: "-3".to_string(),
+			diagnose: false,
+		};
+		let response =
+			CountdownExecutor::execute(serde_json::to_string(&request).unwrap().as_str());
+		let Response::Error(error) = serde_json::from_str(response.as_str()).unwrap() else {
+			panic!("expected an error response");
+		};
+		assert_eq!(error.code, "negative_step");
+		assert_eq!(error.details, Some(serde_json::json!(-3)));
+		assert_eq!(error.origin, ErrorOrigin::State);
+	}
+
+	#[test]
+	fn test_diagnose() {
+		let state = MyExecutor::execute_event(
+			MyExecutor::execute_initialization("null".to_string())
+				.unwrap()
+				.state,
+			"\"a\"".to_string(),
+		)
+		.unwrap()
+		.state;
+		let request = Request::Event {
+			state,
+			event: "true".to_string(),
+			diagnose: true,
+		};
+		let response = MyExecutor::execute(serde_json::to_string(&request).unwrap().as_str());
+		let Response::Error(error) = serde_json::from_str(response.as_str()).unwrap() else {
+			panic!("expected an error response");
+		};
+		assert_eq!(error.code, "event_dropped");
+		assert_eq!(error.origin, ErrorOrigin::Executor);
+		let diagnostics: Vec<serde_json::Value> =
+			serde_json::from_value(error.details.unwrap()).unwrap();
+		let states: Vec<&str> = diagnostics
+			.iter()
+			.map(|diagnostic| diagnostic["state"].as_str().unwrap())
+			.collect();
+		assert_eq!(
+			states,
+			vec!["guest::tests::MyState", "guest::tests::SuperState"]
+		);
+		assert!(diagnostics.iter().all(|diagnostic| diagnostic["error"]
+			.as_str()
+			.unwrap()
+			.contains("invalid type")));
+	}
+
+	#[test]
+	fn test_describe() {
+		let Response::Schemas(schemas) = MyExecutor::serialized_execute(Request::Describe).unwrap()
+		else {
+			panic!("expected schemas");
+		};
+		let parameter: serde_json::Value =
+			serde_json::from_str(schemas.parameter.as_str()).unwrap();
+		assert_eq!(parameter["type"], "null");
+		let event: serde_json::Value = serde_json::from_str(schemas.event.as_str()).unwrap();
+		let types: Vec<&str> = event["anyOf"]
+			.as_array()
+			.unwrap()
+			.iter()
+			.map(|schema| schema["type"].as_str().unwrap())
+			.collect();
+		assert_eq!(types, vec!["integer", "string"]);
+		let state: serde_json::Value = serde_json::from_str(schemas.state.as_str()).unwrap();
+		assert_eq!(state["$ref"], "#/definitions/SuperState");
+		assert!(state["definitions"]["MyState"].is_object());
+	}
+
+	#[test]
+	fn test_query() {
+		let state = CountdownExecutor::execute_initialization("5".to_string())
+			.unwrap()
+			.state;
+		let query = |query: &str| {
+			let request = Request::Query {
+				state: state.clone(),
+				query: query.to_string(),
+			};
+			let response =
+				CountdownExecutor::execute(serde_json::to_string(&request).unwrap().as_str());
+			serde_json::from_str::<Response>(response.as_str()).unwrap()
+		};
+		let Response::QueryResult(result) = query("\"Remaining\"") else {
+			panic!("expected a query result");
+		};
+		assert_eq!(result, "5");
+		let Response::QueryResult(result) = query("{\"StepsOf\":2}") else {
+			panic!("expected a query result");
+		};
+		assert_eq!(result, "3");
+		let Response::Error(error) = query("{\"StepsOf\":0}") else {
+			panic!("expected an error response");
+		};
+		assert_eq!(error.code, "invalid_step");
+		let Response::Error(error) = query("\"Elapsed\"") else {
+			panic!("expected an error response");
+		};
+		assert_eq!(error.code, "invalid_query");
+	}
+
+	#[test]
+	fn test_events() {
+		let state = CountdownExecutor::execute_initialization("10".to_string())
+			.unwrap()
+			.state;
+		let events = |events: &[&str]| {
+			let request = Request::Events {
+				state: state.clone(),
+				events: events.iter().map(|event| event.to_string()).collect(),
+			};
+			let response =
+				CountdownExecutor::execute(serde_json::to_string(&request).unwrap().as_str());
+			serde_json::from_str::<Response>(response.as_str()).unwrap()
+		};
+		let Response::Snapshot(snapshot) = events(&["1", "\"x\"", "0"]) else {
+			panic!("expected a snapshot");
+		};
+		assert_eq!(snapshot.state, "9");
+		assert_eq!(snapshot.event_status, EventStatus::Consumed);
+		assert_eq!(
+			snapshot.event_statuses,
+			vec![
+				EventStatus::Consumed,
+				EventStatus::Dropped,
+				EventStatus::Consumed
+			]
+		);
+		assert_eq!(snapshot.operations.len(), 1);
+
+		let Response::Error(error) = events(&["1", "-1", "2"]) else {
+			panic!("expected an error response");
+		};
+		assert_eq!(error.code, "negative_step");
+		assert_eq!(error.message, "event 1: cannot count up");
+
+		let Response::Snapshot(snapshot) = events(&["4", "6"]) else {
+			panic!("expected a snapshot");
+		};
+		assert_eq!(
+			snapshot.completion,
+			Some(Completion::Completed("\"done\"".to_string()))
+		);
+		let Response::Error(error) = events(&["4", "6", "1", "2"]) else {
+			panic!("expected an error response");
+		};
+		assert_eq!(error.code, "process_completed");
+		assert_eq!(error.origin, ErrorOrigin::Executor);
+		assert_eq!(error.message, "event 2: process completed at event 1");
+	}
 }
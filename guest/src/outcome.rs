@@ -0,0 +1,26 @@
+use common::Completion;
+
+use crate::event::OutgoingEvent;
+
+/// Final result of a workflow, reported by [`State::outcome`](crate::State::outcome).
+pub enum Outcome {
+	Completed(Box<dyn OutgoingEvent>),
+	Failed(Box<dyn OutgoingEvent>),
+}
+
+impl Outcome {
+	pub fn completed<T: OutgoingEvent + 'static>(result: T) -> Self {
+		Outcome::Completed(Box::new(result))
+	}
+
+	pub fn failed<T: OutgoingEvent + 'static>(result: T) -> Self {
+		Outcome::Failed(Box::new(result))
+	}
+
+	pub fn build(self) -> anyhow::Result<Completion> {
+		Ok(match self {
+			Outcome::Completed(result) => Completion::Completed(result.get_raw()?),
+			Outcome::Failed(result) => Completion::Failed(result.get_raw()?),
+		})
+	}
+}
@@ -0,0 +1,64 @@
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::{RootSchema, Schema, SchemaObject, SubschemaValidation};
+
+use common::Schemas;
+
+use crate::state::State;
+use crate::store::Store;
+
+/// Prefix of the definitions every [`Store`] registers the event schema of its state under
+/// while the state schema is generated.
+const EVENT_DEFINITION_PREFIX: &str = "Event::";
+
+/// Registers the event schema of `T`, so that the events of nested stores are found by
+/// generating the schema of the root state.
+pub(crate) fn register_event<T: State>(gen: &mut SchemaGenerator) {
+	let schema = gen.subschema_for::<T::Event>();
+	gen.definitions_mut().insert(
+		format!("{}{}", EVENT_DEFINITION_PREFIX, T::schema_name()),
+		schema,
+	);
+}
+
+/// Generates the schemas of the parameter of `T`, of the events accepted by `T` or any store
+/// nested in it, of `T` itself and of its queries.
+pub fn describe<T: State>() -> anyhow::Result<Schemas> {
+	let mut gen = SchemaSettings::draft07().into_generator();
+	let parameter = gen.subschema_for::<T::Parameter>();
+	let state = gen.subschema_for::<Store<T>>();
+	let query = gen.subschema_for::<T::Query>();
+	let mut definitions = gen.take_definitions();
+	let event_keys: Vec<String> = definitions
+		.keys()
+		.filter(|key| key.starts_with(EVENT_DEFINITION_PREFIX))
+		.cloned()
+		.collect();
+	let mut events: Vec<Schema> = event_keys
+		.iter()
+		.filter_map(|key| definitions.remove(key))
+		.collect();
+	let event = match events.len() {
+		1 => events.remove(0),
+		_ => Schema::Object(SchemaObject {
+			subschemas: Some(Box::new(SubschemaValidation {
+				any_of: Some(events),
+				..Default::default()
+			})),
+			..Default::default()
+		}),
+	};
+	let root = |schema: Schema| -> anyhow::Result<String> {
+		let root = RootSchema {
+			meta_schema: gen.settings().meta_schema.clone(),
+			schema: schema.into_object(),
+			definitions: definitions.clone(),
+		};
+		Ok(serde_json::to_string(&root)?)
+	};
+	Ok(Schemas {
+		parameter: root(parameter)?,
+		event: root(event)?,
+		state: root(state)?,
+		query: root(query)?,
+	})
+}
@@ -1,19 +1,60 @@
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub use common::EventStatus;
 
 use crate::actions::Actions;
+use crate::error::Error;
+use crate::outcome::Outcome;
 use crate::store::BaseStore;
 
-pub trait State: Eq + Clone + Serialize + for<'a> Deserialize<'a> {
-	type Event: Serialize + for<'a> Deserialize<'a>;
-	type Parameter: for<'a> Deserialize<'a>;
+pub trait State: Eq + Clone + Serialize + for<'a> Deserialize<'a> + JsonSchema {
+	type Event: Serialize + for<'a> Deserialize<'a> + JsonSchema;
+	type Parameter: for<'a> Deserialize<'a> + JsonSchema;
+	/// Read-only questions [`State::query`] answers. Use `()` if there are none.
+	type Query: for<'a> Deserialize<'a> + JsonSchema;
+
 	fn entry(parameter: Self::Parameter) -> (Self, Actions);
 	fn process(self, event: Self::Event) -> (Self, EventStatus, Actions);
+
+	/// Fallible form of `entry`, which is what the executor calls. The default calls `entry`.
+	/// An override replaces that call, so it has to call `entry` itself once `parameter` is
+	/// accepted. An error rejects the parameter: no process is created and the host receives
+	/// the error.
+	fn try_entry(parameter: Self::Parameter) -> Result<(Self, Actions), Error> {
+		Ok(Self::entry(parameter))
+	}
+
+	/// Fallible form of `process`, which is what the executor calls. The default calls
+	/// `process`. An override replaces that call, so it has to call `process` itself once
+	/// `event` is accepted. An error aborts the whole execution, including the processing done
+	/// by other stores: the previous state is kept and the host receives the error.
+	fn try_process(self, event: Self::Event) -> Result<(Self, EventStatus, Actions), Error> {
+		Ok(self.process(event))
+	}
+
 	fn update(self) -> (Self, Actions);
 	fn inner_store(&mut self) -> Vec<&mut dyn BaseStore>;
-}
 
-#[derive(Eq, PartialEq)]
-pub enum EventStatus {
-	Consumed,
-	Dropped,
+	/// Answers `query` from the current state. Nothing the query does is stored.
+	fn query(&self, _query: Self::Query) -> Result<Value, Error> {
+		Err(Error::new(
+			"query_not_supported",
+			"this state answers no queries",
+		))
+	}
+
+	/// Returns `Some` once the workflow is final; the host rejects any further events.
+	fn outcome(&self) -> Option<Outcome> {
+		None
+	}
+
+	/// Values the host indexes the process under, keyed by index name, so it can be found
+	/// without knowing its id.
+	fn indexes(&self) -> BTreeMap<String, String> {
+		BTreeMap::new()
+	}
 }
@@ -1,6 +1,15 @@
+use std::collections::BTreeMap;
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::actions::Actions;
+use crate::diagnostic::Diagnostic;
+use crate::error::Error;
+use crate::outcome::Outcome;
+use crate::schema::register_event;
 use crate::state::{EventStatus, State};
 
 #[derive(Clone, Eq, PartialEq, Debug)]
@@ -14,6 +23,14 @@ impl<T: State> Store<T> {
 			state: Box::new(state),
 		}
 	}
+
+	pub fn outcome(&self) -> Option<Outcome> {
+		self.state.outcome()
+	}
+
+	pub fn indexes(&self) -> BTreeMap<String, String> {
+		self.state.indexes()
+	}
 }
 
 impl<T: State> Serialize for Store<T> {
@@ -21,7 +38,7 @@ impl<T: State> Serialize for Store<T> {
 	where
 		S: Serializer,
 	{
-		return self.state.serialize(serializer);
+		self.state.serialize(serializer)
 	}
 }
 
@@ -31,24 +48,53 @@ impl<'de, T: State> Deserialize<'de> for Store<T> {
 		D: Deserializer<'de>,
 	{
 		let state = T::deserialize(deserializer)?;
-		return Ok(Store::new(state));
+		Ok(Store::new(state))
+	}
+}
+
+/// Same schema as `T`. Generating it also registers the events `T` accepts.
+impl<T: State> JsonSchema for Store<T> {
+	fn is_referenceable() -> bool {
+		false
+	}
+
+	fn schema_name() -> String {
+		T::schema_name()
+	}
+
+	fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+		register_event::<T>(gen);
+		gen.subschema_for::<T>()
 	}
 }
 
 impl<T: State> BaseStore for Store<T> {
-	fn process(&mut self, event: &str) -> (EventStatus, Actions) {
+	fn process(&mut self, event: &str) -> anyhow::Result<(EventStatus, Actions)> {
 		for store in self.state.inner_store() {
-			let (event_status, actions) = store.process(event);
+			let (event_status, actions) = store.process(event)?;
 			if event_status == EventStatus::Consumed {
-				return (event_status, actions);
+				return Ok((event_status, actions));
 			}
 		}
 		if let Ok(event) = serde_json::from_str::<T::Event>(event) {
-			let (new_state, event_status, actions) = T::process(self.state.as_ref().clone(), event);
+			let (new_state, event_status, actions) =
+				T::try_process(self.state.as_ref().clone(), event).map_err(Error::into_anyhow)?;
 			*self.state = new_state;
-			return (event_status, actions);
+			return Ok((event_status, actions));
+		}
+		Ok((EventStatus::Dropped, Actions::new()))
+	}
+
+	fn diagnose(&mut self, event: &str, diagnostics: &mut Vec<Diagnostic>) {
+		for store in self.state.inner_store() {
+			store.diagnose(event, diagnostics);
 		}
-		(EventStatus::Dropped, Actions::new())
+		diagnostics.push(Diagnostic {
+			state: std::any::type_name::<T>(),
+			error: serde_json::from_str::<T::Event>(event)
+				.err()
+				.map(|error| error.to_string()),
+		});
 	}
 
 	fn update(&mut self) -> (StateStatus, Actions) {
@@ -72,8 +118,10 @@ impl<T: State> BaseStore for Store<T> {
 }
 
 pub trait BaseStore {
-	fn process(&mut self, event: &str) -> (EventStatus, Actions);
+	fn process(&mut self, event: &str) -> anyhow::Result<(EventStatus, Actions)>;
 	fn update(&mut self) -> (StateStatus, Actions);
+	/// Explains, for this store and every store nested in it, why `event` was not consumed.
+	fn diagnose(&mut self, event: &str, diagnostics: &mut Vec<Diagnostic>);
 }
 
 #[derive(Eq, PartialEq)]
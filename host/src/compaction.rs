@@ -0,0 +1,51 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::db::{form_key, unix_timestamp};
+use crate::error::HostError;
+use crate::AppState;
+
+const PAGE_SIZE: usize = 100;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Periodically removes history that is covered by a snapshot older than `after_days` days.
+pub async fn run(app_state: Arc<AppState>, after_days: u64, interval_secs: u64) {
+	let mut interval = tokio::time::interval(Duration::from_secs(interval_secs.max(1)));
+	loop {
+		interval.tick().await;
+		match compact(&app_state, after_days) {
+			Ok(count) => println!("history_compaction: {} entries", count),
+			Err(error) => println!("history_compaction_failed: {}", error),
+		}
+	}
+}
+
+fn compact(app_state: &AppState, after_days: u64) -> anyhow::Result<usize> {
+	let cutoff = unix_timestamp().saturating_sub(after_days * SECONDS_PER_DAY);
+	let mut cursor: Option<String> = None;
+	let mut count = 0;
+	loop {
+		let page = app_state
+			.db_handler
+			.processes(cursor.as_deref(), PAGE_SIZE)?;
+		cursor = page
+			.last()
+			.map(|(wasm, process_id)| form_key(wasm.as_str(), process_id.as_str()));
+		for (wasm, process_id) in page.iter() {
+			// A process written or removed since the scan is compacted on the next run.
+			match app_state.db_handler.compact(wasm, process_id, cutoff) {
+				Ok(removed) => count += removed,
+				Err(error)
+					if matches!(
+						error.downcast_ref::<HostError>(),
+						Some(HostError::Conflict("version_conflict"))
+							| Some(HostError::NotFound("process_not_found"))
+					) => {}
+				Err(error) => return Err(error),
+			}
+		}
+		if page.len() < PAGE_SIZE {
+			return Ok(count);
+		}
+	}
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+const CONFIG_PATH_VARIABLE: &str = "HOST_CONFIG";
+const DEFAULT_CONFIG_PATH: &str = "host.json";
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Config {
+	pub wasm_directory: String,
+	pub store: StoreConfig,
+	pub retention: HashMap<String, RetentionRule>,
+	pub retention_interval_secs: u64,
+	pub snapshot: SnapshotConfig,
+	pub encryption: Option<EncryptionConfig>,
+	pub idempotency_window_secs: u64,
+	pub dispatcher: Option<DispatcherConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum StoreConfig {
+	Sled { path: String },
+	Sqlite { path: String },
+	Memory,
+}
+
+/// Applies `action` to completed or failed processes of a module that have not been written for
+/// `after_days` days. Active and errored processes are only swept when `completed_only` is
+/// explicitly set to `false`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct RetentionRule {
+	pub after_days: u64,
+	pub action: RetentionAction,
+	#[serde(default = "completed_only_default")]
+	pub completed_only: bool,
+}
+
+fn completed_only_default() -> bool {
+	true
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionAction {
+	Delete,
+	Archive,
+}
+
+/// Stores the full state of a process every `every_events` events or once `every_bytes` bytes of
+/// history were written since the previous snapshot. History covered by a snapshot older than
+/// `compact_after_days` days is removed by the compaction sweep.
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(default)]
+pub struct SnapshotConfig {
+	pub every_events: Option<u64>,
+	pub every_bytes: Option<u64>,
+	pub compact_after_days: Option<u64>,
+}
+
+impl SnapshotConfig {
+	pub fn is_due(&self, events: u64, bytes: u64) -> bool {
+		self.every_events.is_some_and(|every| events >= every)
+			|| self.every_bytes.is_some_and(|every| bytes >= every)
+	}
+}
+
+/// Encrypts stored state with the keys in `key_file` and rewraps values under older keys every
+/// `reencryption_interval_secs` seconds.
+#[derive(Deserialize, Debug)]
+pub struct EncryptionConfig {
+	pub key_file: String,
+	pub reencryption_interval_secs: Option<u64>,
+}
+
+/// Posts every pending outbox entry as JSON to `url`, looking for new entries every
+/// `interval_secs` seconds.
+#[derive(Deserialize, Debug, Clone)]
+pub struct DispatcherConfig {
+	pub url: String,
+	#[serde(default = "dispatch_interval_default")]
+	pub interval_secs: u64,
+}
+
+fn dispatch_interval_default() -> u64 {
+	5
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		Config {
+			wasm_directory: "wasm-files".to_string(),
+			store: StoreConfig::Sled {
+				path: "process-db".to_string(),
+			},
+			retention: HashMap::new(),
+			retention_interval_secs: 3600,
+			snapshot: SnapshotConfig::default(),
+			encryption: None,
+			idempotency_window_secs: 24 * 60 * 60,
+			dispatcher: None,
+		}
+	}
+}
+
+impl Config {
+	/// Reads the file named by `HOST_CONFIG`, or `host.json` if present, falling back to defaults.
+	pub fn load() -> anyhow::Result<Config> {
+		let path = std::env::var(CONFIG_PATH_VARIABLE).ok();
+		let path = match path {
+			Some(path) => path,
+			None if Path::new(DEFAULT_CONFIG_PATH).exists() => DEFAULT_CONFIG_PATH.to_string(),
+			None => return Ok(Config::default()),
+		};
+		let content = std::fs::read_to_string(path)?;
+		Ok(serde_json::from_str(content.as_str())?)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_retention_rule_defaults_to_finished_processes() {
+		let rule: RetentionRule =
+			serde_json::from_str("{\"after_days\":30,\"action\":\"delete\"}").unwrap();
+		assert!(rule.completed_only);
+		let rule: RetentionRule = serde_json::from_str(
+			"{\"after_days\":30,\"action\":\"archive\",\"completed_only\":false}",
+		)
+		.unwrap();
+		assert!(!rule.completed_only);
+	}
+}
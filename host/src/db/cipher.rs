@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use serde::{Deserialize, Serialize};
+
+/// First byte of every encrypted value. It can never start a JSON document, so values written
+/// before encryption was enabled are still read as plaintext.
+const ENCRYPTED_MARKER: u8 = 1;
+
+/// Contents of the key file: every key that may still be in use, hex encoded, and the id of the
+/// key new values are wrapped with.
+#[derive(Deserialize)]
+struct KeyFile {
+	active_key: String,
+	keys: HashMap<String, String>,
+}
+
+/// A value encrypted with its own data key, which is itself encrypted with the key `key_id`.
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+	key_id: String,
+	wrapped_key: String,
+	key_nonce: String,
+	nonce: String,
+	ciphertext: String,
+}
+
+/// Envelope encryption of stored values with keys loaded from a local key file.
+pub struct Cipher {
+	active_key: String,
+	keys: HashMap<String, Aes256Gcm>,
+}
+
+impl Cipher {
+	pub fn load(path: &str) -> anyhow::Result<Cipher> {
+		let content = std::fs::read_to_string(path)?;
+		let key_file: KeyFile = serde_json::from_str(content.as_str())?;
+		let mut keys = HashMap::new();
+		for (key_id, key) in key_file.keys {
+			let key = Aes256Gcm::new_from_slice(hex::decode(key)?.as_slice())
+				.map_err(|_| anyhow::Error::msg(format!("invalid_key_length: {}", key_id)))?;
+			keys.insert(key_id, key);
+		}
+		Cipher::new(key_file.active_key, keys)
+	}
+
+	/// A cipher with a single random key, for tests.
+	#[cfg(test)]
+	pub fn temporary() -> Cipher {
+		let key = Aes256Gcm::new(&Aes256Gcm::generate_key(&mut OsRng));
+		Cipher::new(
+			"test".to_string(),
+			HashMap::from([("test".to_string(), key)]),
+		)
+		.unwrap()
+	}
+
+	fn new(active_key: String, keys: HashMap<String, Aes256Gcm>) -> anyhow::Result<Cipher> {
+		if !keys.contains_key(active_key.as_str()) {
+			return Err(anyhow::Error::msg(format!("unknown_key: {}", active_key)));
+		}
+		Ok(Cipher { active_key, keys })
+	}
+
+	/// Encrypts `value` under a fresh data key. `key` is authenticated with the value, so an
+	/// encrypted value cannot be moved to another key.
+	pub fn encrypt(&self, key: &str, value: &[u8]) -> anyhow::Result<Vec<u8>> {
+		let data_key = Aes256Gcm::generate_key(&mut OsRng);
+		let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+		let ciphertext = Aes256Gcm::new(&data_key)
+			.encrypt(
+				&nonce,
+				Payload {
+					msg: value,
+					aad: key.as_bytes(),
+				},
+			)
+			.map_err(|_| anyhow::Error::msg("encryption_failed"))?;
+		let (wrapped_key, key_nonce) = self.wrap(data_key.as_slice())?;
+		encode(&Envelope {
+			key_id: self.active_key.clone(),
+			wrapped_key,
+			key_nonce,
+			nonce: hex::encode(nonce),
+			ciphertext: hex::encode(ciphertext),
+		})
+	}
+
+	/// Decrypts a value written by [`Cipher::encrypt`] and passes plaintext values through.
+	pub fn decrypt(&self, key: &str, value: &[u8]) -> anyhow::Result<Vec<u8>> {
+		let Some(envelope) = decode(value)? else {
+			return Ok(value.to_vec());
+		};
+		let data_key = self.unwrap(&envelope)?;
+		let data_key = Aes256Gcm::new_from_slice(data_key.as_slice())
+			.map_err(|_| anyhow::Error::msg("decryption_failed"))?;
+		data_key
+			.decrypt(
+				Nonce::from_slice(hex::decode(envelope.nonce)?.as_slice()),
+				Payload {
+					msg: hex::decode(envelope.ciphertext)?.as_slice(),
+					aad: key.as_bytes(),
+				},
+			)
+			.map_err(|_| anyhow::Error::msg("decryption_failed"))
+	}
+
+	/// Returns `true` if `value` is plaintext or wrapped with a key other than the active one.
+	pub fn is_stale(&self, value: &[u8]) -> anyhow::Result<bool> {
+		Ok(match decode(value)? {
+			Some(envelope) => envelope.key_id != self.active_key,
+			None => true,
+		})
+	}
+
+	/// Wraps the data key of `value` with the active key, leaving the ciphertext untouched.
+	/// Plaintext values are encrypted.
+	pub fn reencrypt(&self, key: &str, value: &[u8]) -> anyhow::Result<Vec<u8>> {
+		let Some(mut envelope) = decode(value)? else {
+			return self.encrypt(key, value);
+		};
+		let data_key = self.unwrap(&envelope)?;
+		(envelope.wrapped_key, envelope.key_nonce) = self.wrap(data_key.as_slice())?;
+		envelope.key_id = self.active_key.clone();
+		encode(&envelope)
+	}
+
+	fn wrap(&self, data_key: &[u8]) -> anyhow::Result<(String, String)> {
+		let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+		let wrapped_key = self.keys[self.active_key.as_str()]
+			.encrypt(&nonce, data_key)
+			.map_err(|_| anyhow::Error::msg("encryption_failed"))?;
+		Ok((hex::encode(wrapped_key), hex::encode(nonce)))
+	}
+
+	fn unwrap(&self, envelope: &Envelope) -> anyhow::Result<Vec<u8>> {
+		let key = self
+			.keys
+			.get(envelope.key_id.as_str())
+			.ok_or(anyhow::Error::msg(format!(
+				"unknown_key: {}",
+				envelope.key_id
+			)))?;
+		key.decrypt(
+			Nonce::from_slice(hex::decode(envelope.key_nonce.as_str())?.as_slice()),
+			hex::decode(envelope.wrapped_key.as_str())?.as_slice(),
+		)
+		.map_err(|_| anyhow::Error::msg("decryption_failed"))
+	}
+}
+
+fn encode(envelope: &Envelope) -> anyhow::Result<Vec<u8>> {
+	let mut value = vec![ENCRYPTED_MARKER];
+	serde_json::to_writer(&mut value, envelope)?;
+	Ok(value)
+}
+
+fn decode(value: &[u8]) -> anyhow::Result<Option<Envelope>> {
+	match value.split_first() {
+		Some((&ENCRYPTED_MARKER, envelope)) => Ok(Some(serde_json::from_slice(envelope)?)),
+		_ => Ok(None),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn cipher(active_key: &str, key_ids: &[&str]) -> Cipher {
+		let keys = key_ids
+			.iter()
+			.map(|key_id| {
+				let key = Aes256Gcm::new(&Aes256Gcm::generate_key(&mut OsRng));
+				(key_id.to_string(), key)
+			})
+			.collect();
+		Cipher::new(active_key.to_string(), keys).unwrap()
+	}
+
+	#[test]
+	fn test_encrypt_and_rotate() {
+		let mut cipher = cipher("old", &["old", "new"]);
+		let encrypted = cipher.encrypt("a::1", b"{\"secret\":1}").unwrap();
+		assert!(!encrypted
+			.windows(b"secret".len())
+			.any(|window| window == b"secret"));
+		assert_eq!(
+			cipher.decrypt("a::1", encrypted.as_slice()).unwrap(),
+			b"{\"secret\":1}"
+		);
+		assert!(cipher.decrypt("a::2", encrypted.as_slice()).is_err());
+		assert_eq!(cipher.decrypt("a::1", b"{}").unwrap(), b"{}");
+		assert!(!cipher.is_stale(encrypted.as_slice()).unwrap());
+		assert!(cipher.is_stale(b"{}").unwrap());
+
+		cipher.active_key = "new".to_string();
+		assert!(cipher.is_stale(encrypted.as_slice()).unwrap());
+		let rotated = cipher.reencrypt("a::1", encrypted.as_slice()).unwrap();
+		assert!(!cipher.is_stale(rotated.as_slice()).unwrap());
+		cipher.keys.remove("old");
+		assert_eq!(
+			cipher.decrypt("a::1", rotated.as_slice()).unwrap(),
+			b"{\"secret\":1}"
+		);
+		assert!(cipher.decrypt("a::1", encrypted.as_slice()).is_err());
+	}
+}
@@ -0,0 +1,168 @@
+use std::sync::Arc;
+
+use crate::db::cipher::Cipher;
+use crate::db::encrypted_store::EncryptedStore;
+use crate::db::memory_store::MemoryStore;
+use crate::db::process_store::{history_key, Keyspace, ProcessStore, Write};
+use crate::db::sled_store::SledStore;
+use crate::db::sqlite_store::SqliteStore;
+
+fn check_get_insert_delete(store: &dyn ProcessStore) {
+	assert_eq!(store.get(Keyspace::State, "a::1").unwrap(), None);
+	store.insert(Keyspace::State, "a::1", b"one").unwrap();
+	assert_eq!(
+		store.get(Keyspace::State, "a::1").unwrap(),
+		Some(b"one".to_vec())
+	);
+	assert_eq!(store.get(Keyspace::History, "a::1").unwrap(), None);
+	store.insert(Keyspace::State, "a::1", b"two").unwrap();
+	assert_eq!(
+		store.get(Keyspace::State, "a::1").unwrap(),
+		Some(b"two".to_vec())
+	);
+	store.delete(Keyspace::State, "a::1").unwrap();
+	assert_eq!(store.get(Keyspace::State, "a::1").unwrap(), None);
+	store.delete(Keyspace::State, "a::1").unwrap();
+}
+
+fn check_compare_and_swap(store: &dyn ProcessStore) {
+	let writes = [Write::Insert {
+		keyspace: Keyspace::History,
+		key: history_key("a::1", 1),
+		value: b"created".to_vec(),
+	}];
+	assert!(store
+		.compare_and_swap(Keyspace::State, "a::1", None, Some(b"one"), &writes)
+		.unwrap());
+	assert!(!store
+		.compare_and_swap(Keyspace::State, "a::1", None, Some(b"other"), &[])
+		.unwrap());
+	assert_eq!(store.history("a::1").unwrap(), vec![b"created".to_vec()]);
+
+	let writes = [
+		Write::Insert {
+			keyspace: Keyspace::History,
+			key: history_key("a::1", 2),
+			value: b"ignored".to_vec(),
+		},
+		Write::Delete {
+			keyspace: Keyspace::History,
+			key: history_key("a::1", 1),
+		},
+	];
+	assert!(!store
+		.compare_and_swap(
+			Keyspace::State,
+			"a::1",
+			Some(b"two"),
+			Some(b"three"),
+			&writes
+		)
+		.unwrap());
+	assert_eq!(store.history("a::1").unwrap(), vec![b"created".to_vec()]);
+	assert!(store
+		.compare_and_swap(Keyspace::State, "a::1", Some(b"one"), Some(b"two"), &writes)
+		.unwrap());
+	assert_eq!(
+		store.get(Keyspace::State, "a::1").unwrap(),
+		Some(b"two".to_vec())
+	);
+	assert_eq!(store.history("a::1").unwrap(), vec![b"ignored".to_vec()]);
+
+	assert!(store
+		.compare_and_swap(Keyspace::State, "a::1", Some(b"two"), None, &[])
+		.unwrap());
+	assert_eq!(store.get(Keyspace::State, "a::1").unwrap(), None);
+}
+
+fn check_scan(store: &dyn ProcessStore) {
+	for key in ["a::3", "a::1", "b::1", "a::2", "a:::"] {
+		store.insert(Keyspace::State, key, key.as_bytes()).unwrap();
+	}
+	let keys = |entries: Vec<(String, Vec<u8>)>| -> Vec<String> {
+		entries.into_iter().map(|(key, _)| key).collect()
+	};
+	assert_eq!(
+		keys(store.scan(Keyspace::State, "a::", None, 10).unwrap()),
+		vec!["a::1", "a::2", "a::3", "a:::"]
+	);
+	assert_eq!(
+		keys(store.scan(Keyspace::State, "a::", None, 2).unwrap()),
+		vec!["a::1", "a::2"]
+	);
+	assert_eq!(
+		keys(
+			store
+				.scan(Keyspace::State, "a::", Some("a::1"), 10)
+				.unwrap()
+		),
+		vec!["a::2", "a::3", "a:::"]
+	);
+	assert_eq!(
+		keys(store.scan(Keyspace::State, "a::", Some("0"), 1).unwrap()),
+		vec!["a::1"]
+	);
+	assert!(store
+		.scan(Keyspace::State, "a::", Some("a:::"), 10)
+		.unwrap()
+		.is_empty());
+	assert!(store
+		.scan(Keyspace::History, "a::", None, 10)
+		.unwrap()
+		.is_empty());
+}
+
+fn check_history(store: &dyn ProcessStore) {
+	for version in [10, 2, 1] {
+		store
+			.insert(
+				Keyspace::History,
+				history_key("a::1", version).as_str(),
+				version.to_string().as_bytes(),
+			)
+			.unwrap();
+	}
+	store
+		.insert(
+			Keyspace::History,
+			history_key("a::12", 1).as_str(),
+			b"other",
+		)
+		.unwrap();
+	assert_eq!(
+		store.history("a::1").unwrap(),
+		vec![b"1".to_vec(), b"2".to_vec(), b"10".to_vec()]
+	);
+}
+
+fn check(new_store: impl Fn() -> Box<dyn ProcessStore>) {
+	check_get_insert_delete(new_store().as_ref());
+	check_compare_and_swap(new_store().as_ref());
+	check_scan(new_store().as_ref());
+	check_history(new_store().as_ref());
+}
+
+#[test]
+fn test_memory_store() {
+	check(|| Box::new(MemoryStore::new()));
+}
+
+#[test]
+fn test_sled_store() {
+	check(|| Box::new(SledStore::temporary().unwrap()));
+}
+
+#[test]
+fn test_sqlite_store() {
+	check(|| Box::new(SqliteStore::open(":memory:").unwrap()));
+}
+
+#[test]
+fn test_encrypted_store() {
+	check(|| {
+		Box::new(EncryptedStore::new(
+			Arc::new(MemoryStore::new()),
+			Cipher::temporary(),
+		))
+	});
+}
@@ -1,32 +1,1722 @@
-use sled::Db;
+use std::collections::BTreeMap;
+use std::io::{Read, Write as _};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use serde::Deserialize;
+
+use common::{Completion, EventStatus, Operation};
+
+use crate::config::{SnapshotConfig, StoreConfig};
+use crate::db::cipher::Cipher;
+use crate::db::encrypted_store::EncryptedStore;
+use crate::db::memory_store::MemoryStore;
+use crate::db::process_store::{
+	history_key, history_prefix, index_key, index_prefix, outbox_key, Keyspace, ProcessStore, Write,
+};
+use crate::db::record::{
+	ArchivedProcess, CompletionRecord, ExportedProcess, HistoryEntry, HistoryInput,
+	IdempotencyRecord, OutboxEntry, ProcessError, ProcessMetadata, ProcessRecord, ProcessStatus,
+	SnapshotEntry,
+};
+use crate::db::sled_store::SledStore;
+use crate::db::sqlite_store::SqliteStore;
+use crate::error::HostError;
+
+const UPGRADE_PAGE_SIZE: usize = 100;
+const CONSISTENT_READ_ATTEMPTS: usize = 3;
 
 pub struct DbHandler {
-	db: Db,
+	store: Arc<dyn ProcessStore>,
+	encrypted_store: Option<Arc<EncryptedStore>>,
+	snapshot_config: SnapshotConfig,
+	idempotency_window_secs: u64,
+}
+
+/// Result of running the guest, persisted as the next version of a process.
+pub struct Transition<'a> {
+	pub state: &'a str,
+	pub module_hash: &'a str,
+	pub completion: Option<&'a Completion>,
+	pub indexes: &'a BTreeMap<String, String>,
+	pub operations: &'a [Operation],
+	pub event_status: EventStatus,
+	pub idempotency: Option<Idempotency<'a>>,
 }
+
+/// A consumed event leaving `state` and nothing else to store, for tests.
+#[cfg(test)]
+pub fn transition<'a>(state: &'a str, module_hash: &'a str) -> Transition<'a> {
+	static NO_INDEXES: BTreeMap<String, String> = BTreeMap::new();
+	Transition {
+		state,
+		module_hash,
+		completion: None,
+		indexes: &NO_INDEXES,
+		operations: &[],
+		event_status: EventStatus::Consumed,
+		idempotency: None,
+	}
+}
+
+/// Client supplied key under which the result of a write is stored, and a fingerprint of the
+/// request that detects the key being reused for a different request.
+#[derive(Clone, Copy)]
+pub struct Idempotency<'a> {
+	pub key: &'a str,
+	pub fingerprint: &'a str,
+}
+
+/// What an import does with a process that already exists.
+#[derive(Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+	Skip,
+	Overwrite,
+	Fail,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ImportResult {
+	Imported,
+	Overwritten,
+	Skipped,
+}
+
 impl DbHandler {
-	pub fn load_directory(path: &str) -> anyhow::Result<DbHandler> {
-		Ok(DbHandler {
-			db: sled::open(path)?,
-		})
+	pub fn new(store: Box<dyn ProcessStore>) -> DbHandler {
+		DbHandler {
+			store: Arc::from(store),
+			encrypted_store: None,
+			snapshot_config: SnapshotConfig::default(),
+			idempotency_window_secs: 24 * 60 * 60,
+		}
+	}
+
+	pub fn with_idempotency_window(mut self, idempotency_window_secs: u64) -> DbHandler {
+		self.idempotency_window_secs = idempotency_window_secs;
+		self
+	}
+
+	/// Encrypts every stored value except index entries with `cipher` from now on. Values written
+	/// before stay readable and are encrypted by [`DbHandler::reencrypt`].
+	pub fn with_encryption(mut self, cipher: Cipher) -> DbHandler {
+		let encrypted_store = Arc::new(EncryptedStore::new(self.store.clone(), cipher));
+		self.store = encrypted_store.clone();
+		self.encrypted_store = Some(encrypted_store);
+		self
+	}
+
+	pub fn with_snapshots(mut self, snapshot_config: SnapshotConfig) -> DbHandler {
+		self.snapshot_config = snapshot_config;
+		self
 	}
 
-	pub fn insert(&self, wasm: &str, process_id: &str, data: &str) -> anyhow::Result<()> {
-		self.db.insert(
-			form_key(wasm, process_id).as_str().as_bytes(),
-			data.as_bytes(),
+	/// A handler over an in-memory store, for tests.
+	#[cfg(test)]
+	pub fn temporary() -> DbHandler {
+		DbHandler::open(&StoreConfig::Memory).unwrap()
+	}
+
+	pub fn open(config: &StoreConfig) -> anyhow::Result<DbHandler> {
+		let store: Box<dyn ProcessStore> = match config {
+			StoreConfig::Sled { path } => Box::new(SledStore::open(path)?),
+			StoreConfig::Sqlite { path } => Box::new(SqliteStore::open(path)?),
+			StoreConfig::Memory => Box::new(MemoryStore::new()),
+		};
+		let db_handler = DbHandler::new(store);
+		let count = db_handler.upgrade_legacy_records()?;
+		if count > 0 {
+			println!("legacy_records_upgraded: {}", count);
+		}
+		Ok(db_handler)
+	}
+
+	/// Upgrades processes stored before metadata existed: bare state JSON becomes a version 1
+	/// record, and records without metadata get fresh metadata at their version. Upgraded
+	/// processes are skipped, so running it again is a no-op. Returns the number of upgrades.
+	fn upgrade_legacy_records(&self) -> anyhow::Result<usize> {
+		let mut after: Option<String> = None;
+		let mut count = 0;
+		loop {
+			let entries =
+				self.store
+					.scan(Keyspace::State, "", after.as_deref(), UPGRADE_PAGE_SIZE)?;
+			for (key, value) in entries.iter() {
+				let record = match serde_json::from_slice::<ProcessRecord>(value.as_slice()) {
+					Ok(_) if self.store.get(Keyspace::Metadata, key.as_str())?.is_some() => {
+						continue
+					}
+					Ok(record) => record,
+					Err(_)
+						if serde_json::from_slice::<serde_json::Value>(value.as_slice())
+							.is_ok() =>
+					{
+						ProcessRecord {
+							version: 1,
+							state: String::from_utf8(value.clone())?,
+						}
+					}
+					// Encrypted values are never legacy, and they are not JSON either.
+					Err(_) => continue,
+				};
+				let metadata = ProcessMetadata {
+					version: record.version,
+					..initial_metadata(0)
+				};
+				let writes = [metadata_write(key.as_str(), &metadata)?];
+				self.swap(
+					key.as_str(),
+					value.as_slice(),
+					Some(serde_json::to_vec(&record)?.as_slice()),
+					&writes,
+				)?;
+				count += 1;
+			}
+			if entries.len() < UPGRADE_PAGE_SIZE {
+				return Ok(count);
+			}
+			after = entries.last().map(|(key, _)| key.clone());
+		}
+	}
+
+	pub fn insert(
+		&self,
+		wasm: &str,
+		process_id: &str,
+		parameter: &str,
+		transition: &Transition,
+	) -> anyhow::Result<ProcessMetadata> {
+		validate_process_id(process_id)?;
+		let key = form_key(wasm, process_id);
+		let record = ProcessRecord {
+			version: 1,
+			state: transition.state.to_string(),
+		};
+		let mut metadata = initial_metadata(parameter.len() as u64);
+		let history = HistoryInput::Initialization {
+			parameter: parameter.to_string(),
+		};
+		let mut writes = transition_writes(wasm, process_id, &mut metadata, history, transition)?;
+		writes.extend(idempotency_write(
+			idempotency_key(wasm, None, transition),
+			process_id,
+			&metadata,
+			transition,
+			Vec::new(),
+		)?);
+		let swapped = self.store.compare_and_swap(
+			Keyspace::State,
+			key.as_str(),
+			None,
+			Some(serde_json::to_vec(&record)?.as_slice()),
+			writes.as_slice(),
 		)?;
-		Ok(())
+		if !swapped {
+			return Err(HostError::Conflict("process_already_exists").into());
+		}
+		Ok(metadata)
+	}
+
+	pub fn get(&self, wasm: &str, process_id: &str) -> anyhow::Result<ProcessRecord> {
+		let (_, record) = self.current(form_key(wasm, process_id).as_str(), None)?;
+		Ok(record)
+	}
+
+	pub fn get_metadata(&self, wasm: &str, process_id: &str) -> anyhow::Result<ProcessMetadata> {
+		self.metadata(form_key(wasm, process_id).as_str())
+	}
+
+	/// Reads the record and the metadata written with it. Both are written in one transaction
+	/// but read separately, so a write in between shows up as differing versions and the read is
+	/// retried.
+	pub fn get_with_metadata(
+		&self,
+		wasm: &str,
+		process_id: &str,
+	) -> anyhow::Result<(ProcessRecord, ProcessMetadata)> {
+		let key = form_key(wasm, process_id);
+		for _ in 0..CONSISTENT_READ_ATTEMPTS {
+			let (_, record) = self.current(key.as_str(), None)?;
+			let metadata = self.metadata(key.as_str())?;
+			if metadata.version == record.version {
+				return Ok((record, metadata));
+			}
+		}
+		Err(HostError::Conflict("version_conflict").into())
+	}
+
+	pub fn get_completion(&self, wasm: &str, process_id: &str) -> anyhow::Result<CompletionRecord> {
+		let entry = self
+			.store
+			.get(Keyspace::Completion, form_key(wasm, process_id).as_str())?
+			.ok_or(HostError::NotFound("completion_not_found"))?;
+		Ok(serde_json::from_slice(entry.as_slice())?)
+	}
+
+	/// Returns the stored result of a write under the idempotency key `key`, scoped to creates of
+	/// `wasm` or, with `process_id`, to updates of that process. Expired results are ignored.
+	pub fn get_idempotency(
+		&self,
+		wasm: &str,
+		process_id: Option<&str>,
+		key: &str,
+	) -> anyhow::Result<Option<IdempotencyRecord>> {
+		let key = form_key(form_key(wasm, process_id.unwrap_or_default()).as_str(), key);
+		let Some(entry) = self.store.get(Keyspace::Idempotency, key.as_str())? else {
+			return Ok(None);
+		};
+		let record: IdempotencyRecord = serde_json::from_slice(entry.as_slice())?;
+		if record.stored_at + self.idempotency_window_secs < unix_timestamp() {
+			return Ok(None);
+		}
+		Ok(Some(record))
+	}
+
+	/// Removes up to `limit` expired idempotency results after the key `after`. Returns the last
+	/// visited key, or `None` once the keyspace is exhausted, and the number of removed results.
+	pub fn expire_idempotency(
+		&self,
+		after: Option<&str>,
+		limit: usize,
+	) -> anyhow::Result<(Option<String>, usize)> {
+		let cutoff = unix_timestamp().saturating_sub(self.idempotency_window_secs);
+		let entries = self.store.scan(Keyspace::Idempotency, "", after, limit)?;
+		let mut count = 0;
+		for (key, value) in entries.iter() {
+			let record: IdempotencyRecord = serde_json::from_slice(value.as_slice())?;
+			// A result stored again since the scan is left alone.
+			if record.stored_at < cutoff
+				&& self.store.compare_and_swap(
+					Keyspace::Idempotency,
+					key.as_str(),
+					Some(value.as_slice()),
+					None,
+					&[],
+				)? {
+				count += 1;
+			}
+		}
+		let last = match entries.len() < limit {
+			true => None,
+			false => entries.last().map(|(key, _)| key.clone()),
+		};
+		Ok((last, count))
+	}
+
+	pub fn compare_and_swap(
+		&self,
+		wasm: &str,
+		process_id: &str,
+		version: u64,
+		event: &str,
+		transition: &Transition,
+	) -> anyhow::Result<ProcessMetadata> {
+		let history = HistoryInput::Event {
+			event: event.to_string(),
+			status: transition.event_status,
+		};
+		self.swap_transition(wasm, process_id, version, history, transition)
 	}
 
-	pub fn get(&self, wasm: &str, process_id: &str) -> anyhow::Result<String> {
+	/// Stores the result of a batch of events as one version, with `statuses` holding the status
+	/// of each event.
+	pub fn compare_and_swap_events(
+		&self,
+		wasm: &str,
+		process_id: &str,
+		version: u64,
+		events: &[String],
+		statuses: &[EventStatus],
+		transition: &Transition,
+	) -> anyhow::Result<ProcessMetadata> {
+		let history = HistoryInput::Events {
+			events: events.to_vec(),
+			statuses: statuses.to_vec(),
+		};
+		self.swap_transition(wasm, process_id, version, history, transition)
+	}
+
+	fn swap_transition(
+		&self,
+		wasm: &str,
+		process_id: &str,
+		version: u64,
+		history: HistoryInput,
+		transition: &Transition,
+	) -> anyhow::Result<ProcessMetadata> {
+		let key = form_key(wasm, process_id);
+		let (current, _) = self.current(key.as_str(), Some(version))?;
+		let mut metadata = self.metadata(key.as_str())?;
+		if metadata.status.is_final() {
+			return Err(HostError::Conflict("process_completed").into());
+		}
+		let record = ProcessRecord {
+			version: version + 1,
+			state: transition.state.to_string(),
+		};
+		metadata.version = record.version;
+		metadata.updated_at = unix_timestamp();
+		let events = match &history {
+			HistoryInput::Event { event, .. } => vec![event],
+			HistoryInput::Events { events, .. } => events.iter().collect(),
+			HistoryInput::Initialization { .. } => Vec::new(),
+		};
+		metadata.event_count += events.len() as u64;
+		metadata.history_bytes_since_snapshot +=
+			events.iter().map(|event| event.len() as u64).sum::<u64>();
+		let snapshot_due = self.snapshot_config.is_due(
+			metadata.version - metadata.snapshot_version,
+			metadata.history_bytes_since_snapshot,
+		);
+		if snapshot_due {
+			metadata.snapshot_version = metadata.version;
+			metadata.history_bytes_since_snapshot = 0;
+		}
+		let event_statuses = match &history {
+			HistoryInput::Events { statuses, .. } => statuses.clone(),
+			_ => Vec::new(),
+		};
+		let mut writes = transition_writes(wasm, process_id, &mut metadata, history, transition)?;
+		writes.extend(idempotency_write(
+			idempotency_key(wasm, Some(process_id), transition),
+			process_id,
+			&metadata,
+			transition,
+			event_statuses,
+		)?);
+		if snapshot_due {
+			let snapshot = SnapshotEntry {
+				version: metadata.version,
+				taken_at: metadata.updated_at,
+				state: transition.state.to_string(),
+			};
+			writes.push(snapshot_write(key.as_str(), &snapshot)?);
+		}
+		let record = serde_json::to_vec(&record)?;
+		self.swap(
+			key.as_str(),
+			current.as_slice(),
+			Some(record.as_slice()),
+			writes.as_slice(),
+		)?;
+		Ok(metadata)
+	}
+
+	/// Marks a process as errored without touching its state, as long as it is still at `version`.
+	pub fn record_error(
+		&self,
+		wasm: &str,
+		process_id: &str,
+		version: u64,
+		message: &str,
+	) -> anyhow::Result<ProcessMetadata> {
+		let key = form_key(wasm, process_id);
+		let (current, _) = self.current(key.as_str(), Some(version))?;
+		let mut metadata = self.metadata(key.as_str())?;
+		metadata.status = ProcessStatus::Errored;
+		metadata.last_error = Some(ProcessError {
+			message: message.to_string(),
+			occurred_at: unix_timestamp(),
+		});
+		let writes = [metadata_write(key.as_str(), &metadata)?];
+		self.swap(
+			key.as_str(),
+			current.as_slice(),
+			Some(current.as_slice()),
+			&writes,
+		)?;
+		Ok(metadata)
+	}
+
+	/// Removes the state, metadata and history of a process.
+	pub fn delete(&self, wasm: &str, process_id: &str, version: Option<u64>) -> anyhow::Result<()> {
+		let key = form_key(wasm, process_id);
+		let (current, _) = self.current(key.as_str(), version)?;
+		let writes = self.process_deletes(wasm, process_id)?;
+		self.swap(key.as_str(), current.as_slice(), None, writes.as_slice())
+	}
+
+	/// Moves the state, metadata and history of a process into the compressed archive keyspace.
+	pub fn archive(
+		&self,
+		wasm: &str,
+		process_id: &str,
+		version: Option<u64>,
+	) -> anyhow::Result<()> {
+		let key = form_key(wasm, process_id);
+		let (current, record) = self.current(key.as_str(), version)?;
+		let archived_process = ArchivedProcess {
+			archived_at: unix_timestamp(),
+			record,
+			metadata: self.metadata(key.as_str())?,
+			completion: self.get_completion(wasm, process_id).ok(),
+			history: self.history(wasm, process_id)?,
+			snapshots: self.snapshots(wasm, process_id)?,
+		};
+		let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+		encoder.write_all(serde_json::to_vec(&archived_process)?.as_slice())?;
+		let mut writes = self.process_deletes(wasm, process_id)?;
+		writes.push(Write::Insert {
+			keyspace: Keyspace::Archive,
+			key: key.clone(),
+			value: encoder.finish()?,
+		});
+		self.swap(key.as_str(), current.as_slice(), None, writes.as_slice())
+	}
+
+	pub fn get_archive(&self, wasm: &str, process_id: &str) -> anyhow::Result<ArchivedProcess> {
 		let entry = self
-			.db
-			.get(form_key(wasm, process_id).as_str().as_bytes())?
-			.ok_or(anyhow::Error::msg("process_not_found"))?;
-		Ok(std::str::from_utf8(entry.as_ref())?.to_string())
+			.store
+			.get(Keyspace::Archive, form_key(wasm, process_id).as_str())?
+			.ok_or(HostError::NotFound("archive_not_found"))?;
+		let mut decoded = Vec::new();
+		ZlibDecoder::new(entry.as_slice()).read_to_end(&mut decoded)?;
+		Ok(serde_json::from_slice(decoded.as_slice())?)
+	}
+
+	/// Lists the metadata of processes of `wasm` in key order, starting after the process id
+	/// `after`.
+	pub fn list(
+		&self,
+		wasm: &str,
+		after: Option<&str>,
+		limit: usize,
+	) -> anyhow::Result<Vec<(String, ProcessMetadata)>> {
+		let prefix = form_key(wasm, "");
+		let after = after.map(|process_id| form_key(wasm, process_id));
+		let mut processes = Vec::new();
+		for (key, value) in
+			self.store
+				.scan(Keyspace::Metadata, prefix.as_str(), after.as_deref(), limit)?
+		{
+			let process_id = key[prefix.len()..].to_string();
+			processes.push((process_id, serde_json::from_slice(value.as_slice())?));
+		}
+		Ok(processes)
+	}
+
+	/// Rewraps up to `limit` values of every encrypted keyspace with the active key, continuing
+	/// from `cursor`. Returns the cursor to continue from, or `None` once every value is done, and
+	/// the number of rewritten values.
+	pub fn reencrypt(
+		&self,
+		cursor: Option<(Keyspace, String)>,
+		limit: usize,
+	) -> anyhow::Result<(Option<(Keyspace, String)>, usize)> {
+		let encrypted_store = self.encrypted_store.as_ref().ok_or(HostError::bad_input(
+			"encryption_disabled",
+			"no key file is configured",
+		))?;
+		let keyspaces = EncryptedStore::encrypted_keyspaces();
+		let (keyspace, after) = match cursor {
+			Some((keyspace, after)) => (keyspace, Some(after)),
+			None => (keyspaces[0], None),
+		};
+		let (last, count) = encrypted_store.reencrypt(keyspace, after.as_deref(), limit)?;
+		let next = match last {
+			Some(last) => Some((keyspace, last)),
+			None => keyspaces
+				.iter()
+				.skip_while(|other| **other != keyspace)
+				.nth(1)
+				.map(|next| (*next, String::new())),
+		};
+		Ok((next, count))
+	}
+
+	/// Lists the module and process id of up to `limit` live processes of every module in key
+	/// order, starting after the key `after`.
+	pub fn processes(
+		&self,
+		after: Option<&str>,
+		limit: usize,
+	) -> anyhow::Result<Vec<(String, String)>> {
+		let mut processes = Vec::new();
+		for (key, _) in self.store.scan(Keyspace::Metadata, "", after, limit)? {
+			let (wasm, process_id) = key
+				.split_once("::")
+				.ok_or(HostError::Storage(format!("invalid_key: {}", key)))?;
+			processes.push((wasm.to_string(), process_id.to_string()));
+		}
+		Ok(processes)
+	}
+
+	/// Reads up to `limit` live processes of every module in key order, starting after the key
+	/// `after`.
+	pub fn export(
+		&self,
+		after: Option<&str>,
+		limit: usize,
+	) -> anyhow::Result<Vec<ExportedProcess>> {
+		let mut processes = Vec::new();
+		for (wasm, process_id) in self.processes(after, limit)? {
+			let key = form_key(wasm.as_str(), process_id.as_str());
+			let (_, record) = self.current(key.as_str(), None)?;
+			processes.push(ExportedProcess {
+				record,
+				metadata: self.metadata(key.as_str())?,
+				completion: self.get_completion(wasm.as_str(), process_id.as_str()).ok(),
+				history: self.history(wasm.as_str(), process_id.as_str())?,
+				snapshots: self.snapshots(wasm.as_str(), process_id.as_str())?,
+				wasm,
+				process_id,
+			});
+		}
+		Ok(processes)
+	}
+
+	/// Validates `process` and stores it as exported, resolving an existing process with `policy`.
+	/// With `dry_run` nothing is written and the result only reports what would happen.
+	pub fn import(
+		&self,
+		process: &ExportedProcess,
+		policy: ConflictPolicy,
+		dry_run: bool,
+	) -> anyhow::Result<ImportResult> {
+		process.validate()?;
+		let key = form_key(process.wasm.as_str(), process.process_id.as_str());
+		let current = self.store.get(Keyspace::State, key.as_str())?;
+		let (result, mut writes) = match (&current, policy) {
+			(None, _) => (ImportResult::Imported, Vec::new()),
+			(Some(_), ConflictPolicy::Skip) => return Ok(ImportResult::Skipped),
+			(Some(_), ConflictPolicy::Fail) => {
+				return Err(HostError::Conflict("process_already_exists").into())
+			}
+			(Some(_), ConflictPolicy::Overwrite) => (
+				ImportResult::Overwritten,
+				self.process_deletes(process.wasm.as_str(), process.process_id.as_str())?,
+			),
+		};
+		if dry_run {
+			return Ok(result);
+		}
+		writes.push(metadata_write(key.as_str(), &process.metadata)?);
+		for entry in process.history.iter() {
+			writes.push(Write::Insert {
+				keyspace: Keyspace::History,
+				key: history_key(key.as_str(), entry.version),
+				value: serde_json::to_vec(entry)?,
+			});
+		}
+		for snapshot in process.snapshots.iter() {
+			writes.push(snapshot_write(key.as_str(), snapshot)?);
+		}
+		for (name, value) in process.metadata.indexes.iter() {
+			writes.push(index_write(
+				process.wasm.as_str(),
+				process.process_id.as_str(),
+				name,
+				value,
+			));
+		}
+		if let Some(completion) = &process.completion {
+			writes.push(Write::Insert {
+				keyspace: Keyspace::Completion,
+				key: key.clone(),
+				value: serde_json::to_vec(completion)?,
+			});
+		}
+		let swapped = self.store.compare_and_swap(
+			Keyspace::State,
+			key.as_str(),
+			current.as_deref(),
+			Some(serde_json::to_vec(&process.record)?.as_slice()),
+			writes.as_slice(),
+		)?;
+		if !swapped {
+			return Err(HostError::Conflict("version_conflict").into());
+		}
+		Ok(result)
+	}
+
+	/// Lists the metadata of processes of `wasm` whose index `name` currently holds `value`, in
+	/// process id order starting after the process id `after`.
+	pub fn find(
+		&self,
+		wasm: &str,
+		name: &str,
+		value: &str,
+		after: Option<&str>,
+		limit: usize,
+	) -> anyhow::Result<Vec<(String, ProcessMetadata)>> {
+		let prefix = index_prefix(wasm, name, value);
+		let after = after.map(|process_id| index_key(wasm, name, value, process_id));
+		let mut processes = Vec::new();
+		for (_, process_id) in
+			self.store
+				.scan(Keyspace::Index, prefix.as_str(), after.as_deref(), limit)?
+		{
+			let process_id = String::from_utf8(process_id)?;
+			let metadata = self.metadata(form_key(wasm, process_id.as_str()).as_str())?;
+			processes.push((process_id, metadata));
+		}
+		Ok(processes)
+	}
+
+	/// Reads up to `limit` unacknowledged outbox entries in key order, starting after the id
+	/// `after`. Entries of one process are returned in the order they were emitted.
+	pub fn pending_operations(
+		&self,
+		after: Option<&str>,
+		limit: usize,
+	) -> anyhow::Result<Vec<OutboxEntry>> {
+		let mut entries = Vec::new();
+		for (_, value) in self.store.scan(Keyspace::Outbox, "", after, limit)? {
+			entries.push(serde_json::from_slice(value.as_slice())?);
+		}
+		Ok(entries)
+	}
+
+	/// Marks outbox entries as delivered and returns how many of `ids` were still pending. An
+	/// entry is recorded as delivered before it leaves the outbox, so a failure in between only
+	/// delivers it again.
+	pub fn acknowledge(&self, ids: &[String]) -> anyhow::Result<usize> {
+		let mut count = 0;
+		for id in ids {
+			let Some(entry) = self.store.get(Keyspace::Outbox, id.as_str())? else {
+				continue;
+			};
+			let mut entry: OutboxEntry = serde_json::from_slice(entry.as_slice())?;
+			entry.delivered_at = Some(unix_timestamp());
+			self.store.insert(
+				Keyspace::Delivered,
+				id.as_str(),
+				serde_json::to_vec(&entry)?.as_slice(),
+			)?;
+			self.store.delete(Keyspace::Outbox, id.as_str())?;
+			count += 1;
+		}
+		Ok(count)
+	}
+
+	pub fn history(&self, wasm: &str, process_id: &str) -> anyhow::Result<Vec<HistoryEntry>> {
+		let mut history = Vec::new();
+		for value in self.store.history(form_key(wasm, process_id).as_str())? {
+			history.push(serde_json::from_slice(value.as_slice())?);
+		}
+		Ok(history)
+	}
+
+	pub fn snapshots(&self, wasm: &str, process_id: &str) -> anyhow::Result<Vec<SnapshotEntry>> {
+		let key = form_key(wasm, process_id);
+		let mut snapshots = Vec::new();
+		for (_, value) in self.store.scan(
+			Keyspace::Snapshot,
+			history_prefix(key.as_str()).as_str(),
+			None,
+			usize::MAX,
+		)? {
+			snapshots.push(serde_json::from_slice(value.as_slice())?);
+		}
+		Ok(snapshots)
+	}
+
+	/// Returns the latest snapshot of a process and the history written after it, which is all a
+	/// replay needs. Without a snapshot the replay starts from the initialization.
+	pub fn replay_start(
+		&self,
+		wasm: &str,
+		process_id: &str,
+	) -> anyhow::Result<(Option<SnapshotEntry>, Vec<HistoryEntry>)> {
+		let key = form_key(wasm, process_id);
+		let snapshot = self.snapshots(wasm, process_id)?.pop();
+		let after = snapshot
+			.as_ref()
+			.map(|snapshot| history_key(key.as_str(), snapshot.version));
+		let mut history = Vec::new();
+		for (_, value) in self.store.scan(
+			Keyspace::History,
+			history_prefix(key.as_str()).as_str(),
+			after.as_deref(),
+			usize::MAX,
+		)? {
+			history.push(serde_json::from_slice(value.as_slice())?);
+		}
+		Ok((snapshot, history))
+	}
+
+	/// Removes the history covered by the latest snapshot taken at or before `cutoff`, keeping
+	/// every snapshot. Returns the number of removed entries.
+	pub fn compact(&self, wasm: &str, process_id: &str, cutoff: u64) -> anyhow::Result<usize> {
+		let key = form_key(wasm, process_id);
+		let snapshot = self
+			.snapshots(wasm, process_id)?
+			.into_iter()
+			.rev()
+			.find(|snapshot| snapshot.taken_at <= cutoff);
+		let Some(snapshot) = snapshot else {
+			return Ok(0);
+		};
+		let (current, _) = self.current(key.as_str(), None)?;
+		let last_covered = history_key(key.as_str(), snapshot.version);
+		let writes: Vec<Write> = self
+			.store
+			.scan(
+				Keyspace::History,
+				history_prefix(key.as_str()).as_str(),
+				None,
+				usize::MAX,
+			)?
+			.into_iter()
+			.filter(|(history_key, _)| *history_key <= last_covered)
+			.map(|(key, _)| Write::Delete {
+				keyspace: Keyspace::History,
+				key,
+			})
+			.collect();
+		if writes.is_empty() {
+			return Ok(0);
+		}
+		self.swap(
+			key.as_str(),
+			current.as_slice(),
+			Some(current.as_slice()),
+			writes.as_slice(),
+		)?;
+		Ok(writes.len())
+	}
+
+	/// Reads the raw and decoded record under `key`, checking it against `version` if given.
+	fn current(&self, key: &str, version: Option<u64>) -> anyhow::Result<(Vec<u8>, ProcessRecord)> {
+		let current = self
+			.store
+			.get(Keyspace::State, key)?
+			.ok_or(HostError::NotFound("process_not_found"))?;
+		let record: ProcessRecord = serde_json::from_slice(current.as_slice())?;
+		if version.is_some_and(|version| version != record.version) {
+			return Err(HostError::Conflict("version_conflict").into());
+		}
+		Ok((current, record))
+	}
+
+	fn metadata(&self, key: &str) -> anyhow::Result<ProcessMetadata> {
+		let entry = self
+			.store
+			.get(Keyspace::Metadata, key)?
+			.ok_or(HostError::NotFound("process_not_found"))?;
+		Ok(serde_json::from_slice(entry.as_slice())?)
+	}
+
+	fn process_deletes(&self, wasm: &str, process_id: &str) -> anyhow::Result<Vec<Write>> {
+		let key = form_key(wasm, process_id);
+		let key = key.as_str();
+		let entries = self.store.scan(
+			Keyspace::History,
+			history_prefix(key).as_str(),
+			None,
+			usize::MAX,
+		)?;
+		let snapshots = self.store.scan(
+			Keyspace::Snapshot,
+			history_prefix(key).as_str(),
+			None,
+			usize::MAX,
+		)?;
+		let mut writes: Vec<Write> = entries
+			.into_iter()
+			.map(|(key, _)| Write::Delete {
+				keyspace: Keyspace::History,
+				key,
+			})
+			.chain(snapshots.into_iter().map(|(key, _)| Write::Delete {
+				keyspace: Keyspace::Snapshot,
+				key,
+			}))
+			.collect();
+		if let Some(metadata) = self.store.get(Keyspace::Metadata, key)? {
+			let metadata: ProcessMetadata = serde_json::from_slice(metadata.as_slice())?;
+			for (name, value) in metadata.indexes.iter() {
+				writes.push(Write::Delete {
+					keyspace: Keyspace::Index,
+					key: index_key(wasm, name, value, process_id),
+				});
+			}
+		}
+		for keyspace in [Keyspace::Metadata, Keyspace::Completion] {
+			writes.push(Write::Delete {
+				keyspace,
+				key: key.to_string(),
+			});
+		}
+		Ok(writes)
+	}
+
+	/// Replaces the record under `key` if it is still `current`, failing with a conflict otherwise.
+	fn swap(
+		&self,
+		key: &str,
+		current: &[u8],
+		new: Option<&[u8]>,
+		writes: &[Write],
+	) -> anyhow::Result<()> {
+		let swapped =
+			self.store
+				.compare_and_swap(Keyspace::State, key, Some(current), new, writes)?;
+		if !swapped {
+			return Err(HostError::Conflict("version_conflict").into());
+		}
+		Ok(())
+	}
+}
+
+/// Applies `transition` to `metadata` and returns every write that has to accompany the new
+/// record.
+fn transition_writes(
+	wasm: &str,
+	process_id: &str,
+	metadata: &mut ProcessMetadata,
+	input: HistoryInput,
+	transition: &Transition,
+) -> anyhow::Result<Vec<Write>> {
+	let key = form_key(wasm, process_id);
+	let key = key.as_str();
+	let mut writes = Vec::new();
+	for (name, value) in metadata.indexes.iter() {
+		if transition.indexes.get(name) != Some(value) {
+			writes.push(Write::Delete {
+				keyspace: Keyspace::Index,
+				key: index_key(wasm, name, value, process_id),
+			});
+		}
+	}
+	for (name, value) in transition.indexes.iter() {
+		writes.push(index_write(wasm, process_id, name, value));
 	}
+	metadata.indexes = transition.indexes.clone();
+	metadata.module_hash = transition.module_hash.to_string();
+	metadata.status = match transition.completion {
+		Some(Completion::Completed(_)) => ProcessStatus::Completed,
+		Some(Completion::Failed(_)) => ProcessStatus::Failed,
+		None => ProcessStatus::Active,
+	};
+	writes.push(metadata_write(key, metadata)?);
+	writes.push(history_write(key, metadata.version, input)?);
+	for (index, operation) in transition.operations.iter().enumerate() {
+		let Operation::Event(event) = operation else {
+			continue;
+		};
+		let id = outbox_key(key, metadata.version, index);
+		let entry = OutboxEntry {
+			id: id.clone(),
+			wasm: wasm.to_string(),
+			process_id: process_id.to_string(),
+			version: metadata.version,
+			event: event.clone(),
+			created_at: metadata.updated_at,
+			delivered_at: None,
+		};
+		writes.push(Write::Insert {
+			keyspace: Keyspace::Outbox,
+			key: id,
+			value: serde_json::to_vec(&entry)?,
+		});
+	}
+	if let Some(completion) = transition.completion {
+		let completion_record = CompletionRecord {
+			version: metadata.version,
+			completed_at: metadata.updated_at,
+			completion: completion.clone(),
+		};
+		writes.push(Write::Insert {
+			keyspace: Keyspace::Completion,
+			key: key.to_string(),
+			value: serde_json::to_vec(&completion_record)?,
+		});
+	}
+	Ok(writes)
+}
+
+/// Metadata of a process at version 1, before a transition is applied to it.
+fn initial_metadata(history_bytes: u64) -> ProcessMetadata {
+	let now = unix_timestamp();
+	ProcessMetadata {
+		version: 1,
+		created_at: now,
+		updated_at: now,
+		event_count: 0,
+		module_hash: String::new(),
+		status: ProcessStatus::Active,
+		last_error: None,
+		snapshot_version: 0,
+		history_bytes_since_snapshot: history_bytes,
+		indexes: BTreeMap::new(),
+	}
+}
+
+/// Storage key of the idempotency result of `transition`, if it has one. Creates are scoped to
+/// the module, updates to the process.
+fn idempotency_key(
+	wasm: &str,
+	process_id: Option<&str>,
+	transition: &Transition,
+) -> Option<String> {
+	let idempotency = transition.idempotency?;
+	let scope = form_key(wasm, process_id.unwrap_or_default());
+	Some(form_key(scope.as_str(), idempotency.key))
+}
+
+fn idempotency_write(
+	key: Option<String>,
+	process_id: &str,
+	metadata: &ProcessMetadata,
+	transition: &Transition,
+	event_statuses: Vec<EventStatus>,
+) -> anyhow::Result<Option<Write>> {
+	let (Some(key), Some(idempotency)) = (key, transition.idempotency) else {
+		return Ok(None);
+	};
+	let record = IdempotencyRecord {
+		stored_at: metadata.updated_at,
+		fingerprint: idempotency.fingerprint.to_string(),
+		process_id: process_id.to_string(),
+		metadata: metadata.clone(),
+		state: transition.state.to_string(),
+		operations: transition.operations.to_vec(),
+		completion: transition.completion.cloned(),
+		event_status: transition.event_status,
+		event_statuses,
+	};
+	Ok(Some(Write::Insert {
+		keyspace: Keyspace::Idempotency,
+		key,
+		value: serde_json::to_vec(&record)?,
+	}))
+}
+
+fn index_write(wasm: &str, process_id: &str, name: &str, value: &str) -> Write {
+	Write::Insert {
+		keyspace: Keyspace::Index,
+		key: index_key(wasm, name, value, process_id),
+		value: process_id.as_bytes().to_vec(),
+	}
+}
+
+fn metadata_write(key: &str, metadata: &ProcessMetadata) -> anyhow::Result<Write> {
+	Ok(Write::Insert {
+		keyspace: Keyspace::Metadata,
+		key: key.to_string(),
+		value: serde_json::to_vec(metadata)?,
+	})
 }
 
-fn form_key(wasm: &str, process_id: &str) -> String {
+fn snapshot_write(key: &str, snapshot: &SnapshotEntry) -> anyhow::Result<Write> {
+	Ok(Write::Insert {
+		keyspace: Keyspace::Snapshot,
+		key: history_key(key, snapshot.version),
+		value: serde_json::to_vec(snapshot)?,
+	})
+}
+
+fn history_write(key: &str, version: u64, input: HistoryInput) -> anyhow::Result<Write> {
+	Ok(Write::Insert {
+		keyspace: Keyspace::History,
+		key: history_key(key, version),
+		value: serde_json::to_vec(&HistoryEntry { version, input })?,
+	})
+}
+
+/// Accepts ids of 1 to 128 ASCII letters, digits, `-`, `_` and `.`, which can never contain the
+/// `::` key separator.
+pub fn validate_process_id(process_id: &str) -> anyhow::Result<()> {
+	let valid = (1..=128).contains(&process_id.len())
+		&& process_id
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+	match valid {
+		true => Ok(()),
+		false => {
+			Err(HostError::bad_input("invalid_process_id", format!("{:?}", process_id)).into())
+		}
+	}
+}
+
+pub fn form_key(wasm: &str, process_id: &str) -> String {
 	format!("{}::{}", wasm, process_id)
 }
+
+pub fn unix_timestamp() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|duration| duration.as_secs())
+		.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_validate_process_id() {
+		for process_id in ["order-42", "a.b_c", "0b6f4f0e-6a55-4b1e-9a1f-2f5c1f6d9d3e"] {
+			assert!(validate_process_id(process_id).is_ok());
+		}
+		for process_id in ["", "a::b", "a:b", "a b", "ü", &"a".repeat(129)] {
+			assert!(validate_process_id(process_id).is_err());
+		}
+	}
+
+	#[test]
+	fn test_versioned_writes() {
+		let db_handler = DbHandler::new(Box::new(MemoryStore::new()));
+		let metadata = db_handler
+			.insert("a.wasm", "p", "{}", &transition("{}", "hash"))
+			.unwrap();
+		assert_eq!(metadata.version, 1);
+		assert!(db_handler
+			.insert("a.wasm", "p", "{}", &transition("{}", "hash"))
+			.is_err());
+
+		let metadata = db_handler
+			.compare_and_swap("a.wasm", "p", 1, "1", &transition("[1]", "hash"))
+			.unwrap();
+		assert_eq!(metadata.version, 2);
+		let error = db_handler
+			.compare_and_swap("a.wasm", "p", 1, "2", &transition("[2]", "hash"))
+			.unwrap_err();
+		assert_eq!(error.to_string(), "version_conflict");
+
+		db_handler
+			.compare_and_swap(
+				"a.wasm",
+				"p",
+				2,
+				"\"x\"",
+				&Transition {
+					event_status: EventStatus::Dropped,
+					..transition("[1]", "hash")
+				},
+			)
+			.unwrap();
+
+		let record = db_handler.get("a.wasm", "p").unwrap();
+		assert_eq!(record.version, 3);
+		assert_eq!(record.state, "[1]");
+
+		let history = db_handler.history("a.wasm", "p").unwrap();
+		assert_eq!(
+			history,
+			vec![
+				HistoryEntry {
+					version: 1,
+					input: HistoryInput::Initialization {
+						parameter: "{}".to_string()
+					},
+				},
+				HistoryEntry {
+					version: 2,
+					input: HistoryInput::Event {
+						event: "1".to_string(),
+						status: EventStatus::Consumed,
+					},
+				},
+				HistoryEntry {
+					version: 3,
+					input: HistoryInput::Event {
+						event: "\"x\"".to_string(),
+						status: EventStatus::Dropped,
+					},
+				},
+			]
+		);
+		let stored: HistoryInput = serde_json::from_str("{\"Event\":{\"event\":\"1\"}}").unwrap();
+		assert_eq!(
+			stored,
+			HistoryInput::Event {
+				event: "1".to_string(),
+				status: EventStatus::Consumed,
+			}
+		);
+	}
+
+	#[test]
+	fn test_upgrade_legacy_records() {
+		let db_handler = DbHandler::new(Box::new(MemoryStore::new()));
+		db_handler
+			.insert("a.wasm", "p", "{}", &transition("{}", "hash"))
+			.unwrap();
+		db_handler
+			.store
+			.compare_and_swap(Keyspace::State, "a.wasm::q", None, Some(b"[7]"), &[])
+			.unwrap();
+		assert!(db_handler.get("a.wasm", "q").is_err());
+
+		let record = serde_json::to_vec(&ProcessRecord {
+			version: 4,
+			state: "[4]".to_string(),
+		})
+		.unwrap();
+		db_handler
+			.store
+			.compare_and_swap(Keyspace::State, "a.wasm::r", None, Some(&record), &[])
+			.unwrap();
+
+		assert_eq!(db_handler.upgrade_legacy_records().unwrap(), 2);
+		assert_eq!(db_handler.upgrade_legacy_records().unwrap(), 0);
+		assert_eq!(db_handler.get_metadata("a.wasm", "r").unwrap().version, 4);
+		let record = db_handler.get("a.wasm", "q").unwrap();
+		assert_eq!(record.version, 1);
+		assert_eq!(record.state, "[7]");
+		assert_eq!(db_handler.get_metadata("a.wasm", "q").unwrap().version, 1);
+		assert_eq!(db_handler.list("a.wasm", None, 10).unwrap().len(), 3);
+		let metadata = db_handler
+			.compare_and_swap("a.wasm", "q", 1, "1", &transition("[8]", "hash"))
+			.unwrap();
+		assert_eq!(metadata.version, 2);
+	}
+
+	#[test]
+	fn test_batch() {
+		let db_handler = DbHandler::new(Box::new(MemoryStore::new()));
+		db_handler
+			.insert("a.wasm", "p", "{}", &transition("{}", "hash"))
+			.unwrap();
+		let events = ["1".to_string(), "\"x\"".to_string(), "2".to_string()];
+		let statuses = [
+			EventStatus::Consumed,
+			EventStatus::Dropped,
+			EventStatus::Consumed,
+		];
+		let metadata = db_handler
+			.compare_and_swap_events(
+				"a.wasm",
+				"p",
+				1,
+				&events,
+				&statuses,
+				&Transition {
+					idempotency: Some(Idempotency {
+						key: "k",
+						fingerprint: "f",
+					}),
+					..transition("[3]", "hash")
+				},
+			)
+			.unwrap();
+		assert_eq!(metadata.version, 2);
+		assert_eq!(metadata.event_count, 3);
+		let record = db_handler
+			.get_idempotency("a.wasm", Some("p"), "k")
+			.unwrap()
+			.unwrap();
+		assert_eq!(record.event_statuses, statuses.to_vec());
+		assert!(db_handler
+			.compare_and_swap_events(
+				"a.wasm",
+				"p",
+				1,
+				&events,
+				&statuses,
+				&transition("[6]", "hash")
+			)
+			.is_err());
+
+		let history = db_handler.history("a.wasm", "p").unwrap();
+		assert_eq!(
+			history[1].input,
+			HistoryInput::Events {
+				events: events.to_vec(),
+				statuses: statuses.to_vec(),
+			}
+		);
+		assert_eq!(db_handler.get("a.wasm", "p").unwrap().state, "[3]");
+	}
+
+	#[test]
+	fn test_metadata() {
+		let db_handler = DbHandler::new(Box::new(MemoryStore::new()));
+		db_handler
+			.insert("a.wasm", "p", "{}", &transition("{}", "old"))
+			.unwrap();
+		db_handler
+			.compare_and_swap("a.wasm", "p", 1, "1", &transition("[1]", "new"))
+			.unwrap();
+		db_handler
+			.record_error("a.wasm", "p", 2, "update_limit_exceeded")
+			.unwrap();
+		assert!(db_handler.record_error("a.wasm", "p", 1, "stale").is_err());
+
+		let metadata = db_handler
+			.metadata(form_key("a.wasm", "p").as_str())
+			.unwrap();
+		assert_eq!(metadata.version, 2);
+		assert_eq!(metadata.event_count, 1);
+		assert_eq!(metadata.module_hash, "new");
+		assert_eq!(metadata.status, ProcessStatus::Errored);
+		assert_eq!(
+			metadata.last_error.unwrap().message,
+			"update_limit_exceeded"
+		);
+		assert_eq!(db_handler.get("a.wasm", "p").unwrap().state, "[1]");
+		let (record, metadata) = db_handler.get_with_metadata("a.wasm", "p").unwrap();
+		assert_eq!(record.version, metadata.version);
+		assert_eq!(metadata.status, ProcessStatus::Errored);
+
+		let metadata = db_handler
+			.compare_and_swap("a.wasm", "p", 2, "2", &transition("[2]", "new"))
+			.unwrap();
+		assert_eq!(metadata.status, ProcessStatus::Active);
+		assert!(metadata.last_error.is_some());
+	}
+
+	#[test]
+	fn test_list() {
+		let db_handler = DbHandler::new(Box::new(MemoryStore::new()));
+		for (wasm, process_id) in [("a.wasm", "q"), ("b.wasm", "r"), ("a.wasm", "p")] {
+			db_handler
+				.insert(wasm, process_id, "{}", &transition("{}", "hash"))
+				.unwrap();
+		}
+		let processes = db_handler.list("a.wasm", None, 10).unwrap();
+		let process_ids: Vec<&str> = processes.iter().map(|(id, _)| id.as_str()).collect();
+		assert_eq!(process_ids, vec!["p", "q"]);
+		let processes = db_handler.list("a.wasm", Some("p"), 10).unwrap();
+		assert_eq!(processes.len(), 1);
+		assert_eq!(processes[0].0, "q");
+	}
+
+	#[test]
+	fn test_delete_and_archive() {
+		let db_handler = DbHandler::new(Box::new(MemoryStore::new()));
+		db_handler
+			.insert("a.wasm", "p", "{}", &transition("{}", "hash"))
+			.unwrap();
+		db_handler
+			.insert("a.wasm", "q", "{}", &transition("{}", "hash"))
+			.unwrap();
+		db_handler
+			.compare_and_swap("a.wasm", "q", 1, "1", &transition("[1]", "hash"))
+			.unwrap();
+
+		let error = db_handler.delete("a.wasm", "p", Some(2)).unwrap_err();
+		assert_eq!(error.to_string(), "version_conflict");
+		db_handler.delete("a.wasm", "p", Some(1)).unwrap();
+		assert!(db_handler.get("a.wasm", "p").is_err());
+		assert!(db_handler
+			.metadata(form_key("a.wasm", "p").as_str())
+			.is_err());
+		assert!(db_handler.history("a.wasm", "p").unwrap().is_empty());
+
+		db_handler.archive("a.wasm", "q", None).unwrap();
+		assert!(db_handler.get("a.wasm", "q").is_err());
+		assert!(db_handler.list("a.wasm", None, 10).unwrap().is_empty());
+		assert!(db_handler.history("a.wasm", "q").unwrap().is_empty());
+		let archived_process = db_handler.get_archive("a.wasm", "q").unwrap();
+		assert_eq!(archived_process.record.version, 2);
+		assert_eq!(archived_process.record.state, "[1]");
+		assert_eq!(archived_process.metadata.event_count, 1);
+		assert_eq!(archived_process.history.len(), 2);
+	}
+
+	#[test]
+	fn test_completion() {
+		let db_handler = DbHandler::new(Box::new(MemoryStore::new()));
+		db_handler
+			.insert("a.wasm", "p", "{}", &transition("{}", "hash"))
+			.unwrap();
+		assert!(db_handler.get_completion("a.wasm", "p").is_err());
+
+		let completion = Completion::Completed("42".to_string());
+		let metadata = db_handler
+			.compare_and_swap(
+				"a.wasm",
+				"p",
+				1,
+				"1",
+				&Transition {
+					completion: Some(&completion),
+					..transition("[1]", "hash")
+				},
+			)
+			.unwrap();
+		assert_eq!(metadata.status, ProcessStatus::Completed);
+		let completion_record = db_handler.get_completion("a.wasm", "p").unwrap();
+		assert_eq!(completion_record.version, 2);
+		assert_eq!(completion_record.completion, completion);
+
+		let error = db_handler
+			.compare_and_swap("a.wasm", "p", 2, "2", &transition("[2]", "hash"))
+			.unwrap_err();
+		assert_eq!(error.to_string(), "process_completed");
+
+		db_handler.archive("a.wasm", "p", None).unwrap();
+		assert!(db_handler.get_completion("a.wasm", "p").is_err());
+		let archived_process = db_handler.get_archive("a.wasm", "p").unwrap();
+		assert_eq!(archived_process.completion, Some(completion_record));
+	}
+
+	#[test]
+	fn test_export_and_import() {
+		let source = DbHandler::new(Box::new(MemoryStore::new()));
+		for (wasm, process_id) in [("a.wasm", "p"), ("b.wasm", "q")] {
+			source
+				.insert(wasm, process_id, "{}", &transition("{}", "hash"))
+				.unwrap();
+		}
+		source
+			.compare_and_swap("a.wasm", "p", 1, "1", &transition("[1]", "hash"))
+			.unwrap();
+		let exported = source.export(None, 10).unwrap();
+		assert_eq!(exported.len(), 2);
+		assert_eq!(exported[0].history.len(), 2);
+		let after = form_key("a.wasm", "p");
+		assert_eq!(source.export(Some(after.as_str()), 10).unwrap().len(), 1);
+
+		let target = DbHandler::new(Box::new(MemoryStore::new()));
+		target
+			.insert("a.wasm", "p", "{}", &transition("{}", "hash"))
+			.unwrap();
+		let result = target
+			.import(&exported[1], ConflictPolicy::Fail, true)
+			.unwrap();
+		assert_eq!(result, ImportResult::Imported);
+		assert!(target.get("b.wasm", "q").is_err());
+		let error = target
+			.import(&exported[0], ConflictPolicy::Fail, false)
+			.unwrap_err();
+		assert_eq!(error.to_string(), "process_already_exists");
+		let result = target
+			.import(&exported[0], ConflictPolicy::Skip, false)
+			.unwrap();
+		assert_eq!(result, ImportResult::Skipped);
+		assert_eq!(target.get("a.wasm", "p").unwrap().version, 1);
+
+		for process in exported.iter() {
+			target
+				.import(process, ConflictPolicy::Overwrite, false)
+				.unwrap();
+		}
+		assert_eq!(target.export(None, 10).unwrap(), exported);
+
+		let mut invalid = target.export(None, 1).unwrap().remove(0);
+		invalid.record.version = 3;
+		let error = target
+			.import(&invalid, ConflictPolicy::Overwrite, false)
+			.unwrap_err();
+		assert!(error.to_string().starts_with("version_mismatch"));
+	}
+
+	#[test]
+	fn test_snapshots_and_compaction() {
+		let db_handler =
+			DbHandler::new(Box::new(MemoryStore::new())).with_snapshots(SnapshotConfig {
+				every_events: Some(2),
+				every_bytes: None,
+				compact_after_days: None,
+			});
+		db_handler
+			.insert("a.wasm", "p", "{}", &transition("0", "hash"))
+			.unwrap();
+		for version in 1..5 {
+			let state = version.to_string();
+			db_handler
+				.compare_and_swap(
+					"a.wasm",
+					"p",
+					version,
+					"1",
+					&transition(state.as_str(), "hash"),
+				)
+				.unwrap();
+		}
+		let snapshots = db_handler.snapshots("a.wasm", "p").unwrap();
+		let versions: Vec<u64> = snapshots.iter().map(|snapshot| snapshot.version).collect();
+		assert_eq!(versions, vec![2, 4]);
+		assert_eq!(snapshots[1].state, "3");
+
+		let (snapshot, history) = db_handler.replay_start("a.wasm", "p").unwrap();
+		assert_eq!(snapshot.unwrap().version, 4);
+		assert_eq!(history.len(), 1);
+		assert_eq!(history[0].version, 5);
+
+		assert_eq!(db_handler.compact("a.wasm", "p", 0).unwrap(), 0);
+		let cutoff = snapshots[1].taken_at;
+		assert_eq!(db_handler.compact("a.wasm", "p", cutoff).unwrap(), 4);
+		let history = db_handler.history("a.wasm", "p").unwrap();
+		assert_eq!(history.len(), 1);
+		assert_eq!(db_handler.snapshots("a.wasm", "p").unwrap(), snapshots);
+
+		db_handler.delete("a.wasm", "p", None).unwrap();
+		assert!(db_handler.snapshots("a.wasm", "p").unwrap().is_empty());
+	}
+
+	#[test]
+	fn test_indexes() {
+		let db_handler = DbHandler::new(Box::new(MemoryStore::new()));
+		let indexes = BTreeMap::from([("customer_id".to_string(), "42".to_string())]);
+		for process_id in ["p", "q"] {
+			db_handler
+				.insert(
+					"a.wasm",
+					process_id,
+					"{}",
+					&Transition {
+						indexes: &indexes,
+						..transition("{}", "hash")
+					},
+				)
+				.unwrap();
+		}
+		let other = BTreeMap::from([("customer_id".to_string(), "42::x".to_string())]);
+		db_handler
+			.compare_and_swap(
+				"a.wasm",
+				"q",
+				1,
+				"1",
+				&Transition {
+					indexes: &other,
+					..transition("[1]", "hash")
+				},
+			)
+			.unwrap();
+
+		let found = db_handler
+			.find("a.wasm", "customer_id", "42", None, 10)
+			.unwrap();
+		let process_ids: Vec<&str> = found.iter().map(|(id, _)| id.as_str()).collect();
+		assert_eq!(process_ids, vec!["p"]);
+		let found = db_handler
+			.find("a.wasm", "customer_id", "42::x", None, 10)
+			.unwrap();
+		assert_eq!(found[0].0, "q");
+		assert_eq!(found[0].1.indexes, other);
+
+		db_handler.delete("a.wasm", "p", None).unwrap();
+		assert!(db_handler
+			.find("a.wasm", "customer_id", "42", None, 10)
+			.unwrap()
+			.is_empty());
+	}
+
+	#[test]
+	fn test_encryption() {
+		let db_handler = DbHandler::new(Box::new(MemoryStore::new()));
+		db_handler
+			.insert("a.wasm", "p", "{}", &transition("\"plain\"", "hash"))
+			.unwrap();
+		let raw_store = db_handler.store.clone();
+		let db_handler = db_handler.with_encryption(Cipher::temporary());
+		let indexes = BTreeMap::from([("customer_id".to_string(), "42".to_string())]);
+		db_handler
+			.insert(
+				"a.wasm",
+				"q",
+				"{}",
+				&Transition {
+					indexes: &indexes,
+					..transition("\"secret\"", "hash")
+				},
+			)
+			.unwrap();
+		let completion = Completion::Completed("\"result\"".to_string());
+		db_handler
+			.compare_and_swap(
+				"a.wasm",
+				"p",
+				1,
+				"1",
+				&Transition {
+					completion: Some(&completion),
+					..transition("\"next\"", "hash")
+				},
+			)
+			.unwrap();
+		let raw = |keyspace: Keyspace, process_id: &str| {
+			raw_store
+				.get(keyspace, form_key("a.wasm", process_id).as_str())
+				.unwrap()
+				.unwrap()
+		};
+		assert!(
+			serde_json::from_slice::<ProcessRecord>(raw(Keyspace::State, "q").as_slice()).is_err()
+		);
+		assert!(
+			serde_json::from_slice::<ProcessMetadata>(raw(Keyspace::Metadata, "q").as_slice())
+				.is_err()
+		);
+		assert!(serde_json::from_slice::<CompletionRecord>(
+			raw(Keyspace::Completion, "p").as_slice()
+		)
+		.is_err());
+		assert_eq!(db_handler.get("a.wasm", "q").unwrap().state, "\"secret\"");
+		assert_eq!(db_handler.get("a.wasm", "p").unwrap().state, "\"next\"");
+		assert_eq!(
+			db_handler.get_metadata("a.wasm", "q").unwrap().indexes,
+			indexes
+		);
+		assert_eq!(
+			db_handler.get_completion("a.wasm", "p").unwrap().completion,
+			completion
+		);
+		assert_eq!(db_handler.list("a.wasm", None, 10).unwrap().len(), 2);
+		// Index entries are deliberately left in plaintext so lookups can scan for the value.
+		assert_eq!(
+			raw_store
+				.get(
+					Keyspace::Index,
+					index_key("a.wasm", "customer_id", "42", "q").as_str()
+				)
+				.unwrap(),
+			Some(b"q".to_vec())
+		);
+		assert_eq!(
+			db_handler
+				.find("a.wasm", "customer_id", "42", None, 10)
+				.unwrap()
+				.len(),
+			1
+		);
+		let history = raw_store.history(form_key("a.wasm", "p").as_str()).unwrap();
+		assert!(serde_json::from_slice::<HistoryEntry>(history[0].as_slice()).is_ok());
+
+		let mut cursor = None;
+		let mut count = 0;
+		loop {
+			let (next, rewritten) = db_handler.reencrypt(cursor, 1).unwrap();
+			count += rewritten;
+			cursor = match next {
+				Some(next) => Some(next),
+				None => break,
+			};
+		}
+		assert_eq!(count, 1);
+		assert_eq!(db_handler.history("a.wasm", "p").unwrap().len(), 2);
+		let history = raw_store.history(form_key("a.wasm", "p").as_str()).unwrap();
+		assert!(serde_json::from_slice::<HistoryEntry>(history[0].as_slice()).is_err());
+	}
+
+	#[test]
+	fn test_outbox() {
+		let db_handler = DbHandler::new(Box::new(MemoryStore::new()));
+		let operations = [
+			Operation::Event("1".to_string()),
+			Operation::Info("ignored".to_string()),
+			Operation::Event("2".to_string()),
+		];
+		db_handler
+			.insert(
+				"a.wasm",
+				"p",
+				"{}",
+				&Transition {
+					operations: &operations,
+					..transition("{}", "hash")
+				},
+			)
+			.unwrap();
+		db_handler
+			.compare_and_swap(
+				"a.wasm",
+				"p",
+				1,
+				"1",
+				&Transition {
+					operations: &operations[..1],
+					..transition("[1]", "hash")
+				},
+			)
+			.unwrap();
+
+		let pending = db_handler.pending_operations(None, 10).unwrap();
+		let events: Vec<(u64, &str)> = pending
+			.iter()
+			.map(|entry| (entry.version, entry.event.as_str()))
+			.collect();
+		assert_eq!(events, vec![(1, "1"), (1, "2"), (2, "1")]);
+		let after = pending[0].id.as_str();
+		assert_eq!(
+			db_handler.pending_operations(Some(after), 10).unwrap(),
+			pending[1..]
+		);
+
+		let ids = vec![pending[0].id.clone(), pending[2].id.clone()];
+		assert_eq!(db_handler.acknowledge(ids.as_slice()).unwrap(), 2);
+		assert_eq!(db_handler.acknowledge(ids.as_slice()).unwrap(), 0);
+		let delivered = |id: &str| db_handler.store.get(Keyspace::Delivered, id).unwrap();
+		let entry: OutboxEntry =
+			serde_json::from_slice(delivered(ids[0].as_str()).unwrap().as_slice()).unwrap();
+		assert!(entry.delivered_at.is_some());
+		assert_eq!(entry.event, pending[0].event);
+		assert!(delivered(pending[1].id.as_str()).is_none());
+		assert_eq!(
+			db_handler.pending_operations(None, 10).unwrap(),
+			pending[1..2]
+		);
+	}
+
+	#[test]
+	fn test_idempotency() {
+		let db_handler = DbHandler::new(Box::new(MemoryStore::new()));
+		let idempotency = Idempotency {
+			key: "k",
+			fingerprint: "f",
+		};
+		db_handler
+			.insert(
+				"a.wasm",
+				"p",
+				"{}",
+				&Transition {
+					idempotency: Some(idempotency),
+					..transition("{}", "hash")
+				},
+			)
+			.unwrap();
+		let record = db_handler
+			.get_idempotency("a.wasm", None, "k")
+			.unwrap()
+			.unwrap();
+		assert_eq!(record.process_id, "p");
+		assert_eq!(record.metadata.version, 1);
+		assert!(db_handler
+			.get_idempotency("a.wasm", Some("p"), "k")
+			.unwrap()
+			.is_none());
+
+		db_handler
+			.compare_and_swap(
+				"a.wasm",
+				"p",
+				1,
+				"1",
+				&Transition {
+					idempotency: Some(idempotency),
+					..transition("[1]", "hash")
+				},
+			)
+			.unwrap();
+		let record = db_handler
+			.get_idempotency("a.wasm", Some("p"), "k")
+			.unwrap()
+			.unwrap();
+		assert_eq!(record.state, "[1]");
+		assert_eq!(record.fingerprint, "f");
+		assert_eq!(db_handler.expire_idempotency(None, 10).unwrap(), (None, 0));
+
+		let expired = IdempotencyRecord {
+			stored_at: 0,
+			..record
+		};
+		db_handler
+			.store
+			.insert(
+				Keyspace::Idempotency,
+				"a.wasm::::old",
+				serde_json::to_vec(&expired).unwrap().as_slice(),
+			)
+			.unwrap();
+		assert!(db_handler
+			.get_idempotency("a.wasm", None, "old")
+			.unwrap()
+			.is_none());
+		assert_eq!(db_handler.expire_idempotency(None, 10).unwrap(), (None, 1));
+		assert!(db_handler
+			.get_idempotency("a.wasm", None, "k")
+			.unwrap()
+			.is_some());
+	}
+}
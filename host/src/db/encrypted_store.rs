@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use crate::db::cipher::Cipher;
+use crate::db::process_store::{Keyspace, ProcessStore, Write};
+
+/// Keyspaces whose values contain process state or data derived from it.
+///
+/// Index entries stay plaintext: their keys hold the indexed values so that lookups can scan
+/// for them, and their values only hold process ids. Guests should only index values that may
+/// sit on disk unencrypted, such as ids, rather than customer data.
+const ENCRYPTED_KEYSPACES: [Keyspace; 9] = [
+	Keyspace::State,
+	Keyspace::Metadata,
+	Keyspace::History,
+	Keyspace::Completion,
+	Keyspace::Archive,
+	Keyspace::Snapshot,
+	Keyspace::Outbox,
+	Keyspace::Delivered,
+	Keyspace::Idempotency,
+];
+
+/// Encrypts the values of [`ENCRYPTED_KEYSPACES`] before they reach `inner` and decrypts them on
+/// the way out, so callers only ever see plaintext.
+pub struct EncryptedStore {
+	inner: Arc<dyn ProcessStore>,
+	cipher: Cipher,
+}
+
+impl EncryptedStore {
+	pub fn new(inner: Arc<dyn ProcessStore>, cipher: Cipher) -> EncryptedStore {
+		EncryptedStore { inner, cipher }
+	}
+
+	/// Wraps up to `limit` values of `keyspace` after the key `after` with the active key if they
+	/// are not already. Returns the last visited key, or `None` once the keyspace is exhausted,
+	/// and the number of rewritten values.
+	pub fn reencrypt(
+		&self,
+		keyspace: Keyspace,
+		after: Option<&str>,
+		limit: usize,
+	) -> anyhow::Result<(Option<String>, usize)> {
+		let entries = self.inner.scan(keyspace, "", after, limit)?;
+		let mut count = 0;
+		for (key, value) in entries.iter() {
+			if !self.cipher.is_stale(value.as_slice())? {
+				continue;
+			}
+			let reencrypted = self.cipher.reencrypt(key.as_str(), value.as_slice())?;
+			// A value written since the scan is already encrypted with the active key.
+			if self.inner.compare_and_swap(
+				keyspace,
+				key.as_str(),
+				Some(value.as_slice()),
+				Some(reencrypted.as_slice()),
+				&[],
+			)? {
+				count += 1;
+			}
+		}
+		let last = match entries.len() < limit {
+			true => None,
+			false => entries.last().map(|(key, _)| key.clone()),
+		};
+		Ok((last, count))
+	}
+
+	pub fn encrypted_keyspaces() -> &'static [Keyspace] {
+		&ENCRYPTED_KEYSPACES
+	}
+
+	fn encrypt(&self, keyspace: Keyspace, key: &str, value: &[u8]) -> anyhow::Result<Vec<u8>> {
+		match ENCRYPTED_KEYSPACES.contains(&keyspace) {
+			true => self.cipher.encrypt(key, value),
+			false => Ok(value.to_vec()),
+		}
+	}
+
+	fn decrypt(&self, keyspace: Keyspace, key: &str, value: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+		match ENCRYPTED_KEYSPACES.contains(&keyspace) {
+			true => self.cipher.decrypt(key, value.as_slice()),
+			false => Ok(value),
+		}
+	}
+}
+
+impl ProcessStore for EncryptedStore {
+	fn get(&self, keyspace: Keyspace, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+		self.inner
+			.get(keyspace, key)?
+			.map(|value| self.decrypt(keyspace, key, value))
+			.transpose()
+	}
+
+	fn insert(&self, keyspace: Keyspace, key: &str, value: &[u8]) -> anyhow::Result<()> {
+		let value = self.encrypt(keyspace, key, value)?;
+		self.inner.insert(keyspace, key, value.as_slice())
+	}
+
+	fn delete(&self, keyspace: Keyspace, key: &str) -> anyhow::Result<()> {
+		self.inner.delete(keyspace, key)
+	}
+
+	/// Compares `expected` with the decrypted current value, then swaps against the encrypted
+	/// one. A concurrent re-encryption changes the stored bytes but not the plaintext, so the
+	/// comparison is retried until the plaintext differs or the swap succeeds.
+	fn compare_and_swap(
+		&self,
+		keyspace: Keyspace,
+		key: &str,
+		expected: Option<&[u8]>,
+		new: Option<&[u8]>,
+		writes: &[Write],
+	) -> anyhow::Result<bool> {
+		let new = new
+			.map(|value| self.encrypt(keyspace, key, value))
+			.transpose()?;
+		let mut encrypted_writes = Vec::with_capacity(writes.len());
+		for write in writes {
+			encrypted_writes.push(match write {
+				Write::Insert {
+					keyspace,
+					key,
+					value,
+				} => Write::Insert {
+					keyspace: *keyspace,
+					key: key.clone(),
+					value: self.encrypt(*keyspace, key.as_str(), value.as_slice())?,
+				},
+				Write::Delete { .. } => write.clone(),
+			});
+		}
+		loop {
+			let current = self.inner.get(keyspace, key)?;
+			let decrypted = current
+				.clone()
+				.map(|value| self.decrypt(keyspace, key, value))
+				.transpose()?;
+			if decrypted.as_deref() != expected {
+				return Ok(false);
+			}
+			let swapped = self.inner.compare_and_swap(
+				keyspace,
+				key,
+				current.as_deref(),
+				new.as_deref(),
+				encrypted_writes.as_slice(),
+			)?;
+			if swapped {
+				return Ok(true);
+			}
+		}
+	}
+
+	fn scan(
+		&self,
+		keyspace: Keyspace,
+		prefix: &str,
+		after: Option<&str>,
+		limit: usize,
+	) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+		let mut entries = Vec::new();
+		for (key, value) in self.inner.scan(keyspace, prefix, after, limit)? {
+			let value = self.decrypt(keyspace, key.as_str(), value)?;
+			entries.push((key, value));
+		}
+		Ok(entries)
+	}
+}
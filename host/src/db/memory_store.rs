@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::Mutex;
+
+use crate::db::process_store::{Keyspace, ProcessStore, Write};
+
+#[derive(Default)]
+pub struct MemoryStore {
+	map: Mutex<BTreeMap<Keyspace, BTreeMap<String, Vec<u8>>>>,
+}
+
+impl MemoryStore {
+	pub fn new() -> MemoryStore {
+		MemoryStore::default()
+	}
+}
+
+fn apply(map: &mut BTreeMap<Keyspace, BTreeMap<String, Vec<u8>>>, write: &Write) {
+	match write {
+		Write::Insert {
+			keyspace,
+			key,
+			value,
+		} => {
+			map.entry(*keyspace)
+				.or_default()
+				.insert(key.clone(), value.clone());
+		}
+		Write::Delete { keyspace, key } => {
+			map.entry(*keyspace).or_default().remove(key);
+		}
+	}
+}
+
+impl ProcessStore for MemoryStore {
+	fn get(&self, keyspace: Keyspace, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+		let map = self.map.lock().unwrap();
+		Ok(map
+			.get(&keyspace)
+			.and_then(|entries| entries.get(key).cloned()))
+	}
+
+	fn insert(&self, keyspace: Keyspace, key: &str, value: &[u8]) -> anyhow::Result<()> {
+		let mut map = self.map.lock().unwrap();
+		map.entry(keyspace)
+			.or_default()
+			.insert(key.to_string(), value.to_vec());
+		Ok(())
+	}
+
+	fn delete(&self, keyspace: Keyspace, key: &str) -> anyhow::Result<()> {
+		let mut map = self.map.lock().unwrap();
+		map.entry(keyspace).or_default().remove(key);
+		Ok(())
+	}
+
+	fn compare_and_swap(
+		&self,
+		keyspace: Keyspace,
+		key: &str,
+		expected: Option<&[u8]>,
+		new: Option<&[u8]>,
+		writes: &[Write],
+	) -> anyhow::Result<bool> {
+		let mut map = self.map.lock().unwrap();
+		let current = map.get(&keyspace).and_then(|entries| entries.get(key));
+		if current.map(Vec::as_slice) != expected {
+			return Ok(false);
+		}
+		let swap = match new {
+			Some(value) => Write::Insert {
+				keyspace,
+				key: key.to_string(),
+				value: value.to_vec(),
+			},
+			None => Write::Delete {
+				keyspace,
+				key: key.to_string(),
+			},
+		};
+		apply(&mut map, &swap);
+		for write in writes {
+			apply(&mut map, write);
+		}
+		Ok(true)
+	}
+
+	fn scan(
+		&self,
+		keyspace: Keyspace,
+		prefix: &str,
+		after: Option<&str>,
+		limit: usize,
+	) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+		let map = self.map.lock().unwrap();
+		let entries = match map.get(&keyspace) {
+			Some(entries) => entries,
+			None => return Ok(Vec::new()),
+		};
+		let start = match after {
+			Some(after) if after >= prefix => Bound::Excluded(after),
+			_ => Bound::Included(prefix),
+		};
+		Ok(entries
+			.range::<str, _>((start, Bound::Unbounded))
+			.take_while(|(key, _)| key.starts_with(prefix))
+			.take(limit)
+			.map(|(key, value)| (key.clone(), value.clone()))
+			.collect())
+	}
+}
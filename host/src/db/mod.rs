@@ -1,3 +1,22 @@
-pub use db_handler::DbHandler;
+pub use cipher::Cipher;
+#[cfg(test)]
+pub use db_handler::transition;
+pub use db_handler::{
+	form_key, unix_timestamp, validate_process_id, ConflictPolicy, DbHandler, Idempotency,
+	ImportResult, Transition,
+};
+pub use record::{
+	ArchivedProcess, CompletionRecord, ExportedProcess, HistoryEntry, HistoryInput,
+	IdempotencyRecord, OutboxEntry, ProcessMetadata,
+};
 
+mod cipher;
+#[cfg(test)]
+mod conformance;
 mod db_handler;
+mod encrypted_store;
+mod memory_store;
+mod process_store;
+mod record;
+mod sled_store;
+mod sqlite_store;
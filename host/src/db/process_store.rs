@@ -0,0 +1,123 @@
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Keyspace {
+	State,
+	Metadata,
+	History,
+	Completion,
+	Archive,
+	Snapshot,
+	Index,
+	Outbox,
+	Delivered,
+	Idempotency,
+}
+
+impl Keyspace {
+	pub const ALL: [Keyspace; 10] = [
+		Keyspace::State,
+		Keyspace::Metadata,
+		Keyspace::History,
+		Keyspace::Completion,
+		Keyspace::Archive,
+		Keyspace::Snapshot,
+		Keyspace::Index,
+		Keyspace::Outbox,
+		Keyspace::Delivered,
+		Keyspace::Idempotency,
+	];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Keyspace::State => "state",
+			Keyspace::Metadata => "metadata",
+			Keyspace::History => "history",
+			Keyspace::Completion => "completion",
+			Keyspace::Archive => "archive",
+			Keyspace::Snapshot => "snapshot",
+			Keyspace::Index => "index",
+			Keyspace::Outbox => "outbox",
+			Keyspace::Delivered => "delivered",
+			Keyspace::Idempotency => "idempotency",
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub enum Write {
+	Insert {
+		keyspace: Keyspace,
+		key: String,
+		value: Vec<u8>,
+	},
+	Delete {
+		keyspace: Keyspace,
+		key: String,
+	},
+}
+
+/// Ordered key-value storage backing the [`DbHandler`](crate::db::DbHandler).
+///
+/// Keys are compared bytewise, so scans return entries in the same order on every backend.
+pub trait ProcessStore: Send + Sync {
+	fn get(&self, keyspace: Keyspace, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+
+	fn insert(&self, keyspace: Keyspace, key: &str, value: &[u8]) -> anyhow::Result<()>;
+
+	fn delete(&self, keyspace: Keyspace, key: &str) -> anyhow::Result<()>;
+
+	/// Replaces `key` with `new` if its current value equals `expected` and applies `writes` in
+	/// the same transaction. Returns `false` without writing anything if the comparison fails.
+	fn compare_and_swap(
+		&self,
+		keyspace: Keyspace,
+		key: &str,
+		expected: Option<&[u8]>,
+		new: Option<&[u8]>,
+		writes: &[Write],
+	) -> anyhow::Result<bool>;
+
+	/// Returns up to `limit` entries whose key starts with `prefix` and sorts after `after`.
+	fn scan(
+		&self,
+		keyspace: Keyspace,
+		prefix: &str,
+		after: Option<&str>,
+		limit: usize,
+	) -> anyhow::Result<Vec<(String, Vec<u8>)>>;
+
+	fn history(&self, key: &str) -> anyhow::Result<Vec<Vec<u8>>> {
+		let entries = self.scan(
+			Keyspace::History,
+			history_prefix(key).as_str(),
+			None,
+			usize::MAX,
+		)?;
+		Ok(entries.into_iter().map(|(_, value)| value).collect())
+	}
+}
+
+pub fn history_prefix(key: &str) -> String {
+	format!("{}::", key)
+}
+
+pub fn history_key(key: &str, version: u64) -> String {
+	format!("{}{:020}", history_prefix(key), version)
+}
+
+/// Key of the `index`th operation emitted by the write of `version`, ordered per process.
+pub fn outbox_key(key: &str, version: u64, index: usize) -> String {
+	format!("{}::{:06}", history_key(key, version), index)
+}
+
+/// Key of the `index` entry of a process, escaping `:` so values cannot run into each other.
+pub fn index_key(wasm: &str, name: &str, value: &str, process_id: &str) -> String {
+	format!("{}{}", index_prefix(wasm, name, value), process_id)
+}
+
+pub fn index_prefix(wasm: &str, name: &str, value: &str) -> String {
+	format!("{}::{}::{}::", wasm, escape(name), escape(value))
+}
+
+fn escape(part: &str) -> String {
+	part.replace('%', "%25").replace(':', "%3A")
+}
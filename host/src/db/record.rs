@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use common::{Completion, EventStatus, Operation};
+
+use crate::db::validate_process_id;
+use crate::error::HostError;
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct ProcessRecord {
+	pub version: u64,
+	pub state: String,
+}
+
+/// Bookkeeping kept next to each process record so operators can inspect a process without
+/// decoding its state.
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct ProcessMetadata {
+	pub version: u64,
+	pub created_at: u64,
+	pub updated_at: u64,
+	pub event_count: u64,
+	pub module_hash: String,
+	pub status: ProcessStatus,
+	pub last_error: Option<ProcessError>,
+	#[serde(default)]
+	pub snapshot_version: u64,
+	#[serde(default)]
+	pub history_bytes_since_snapshot: u64,
+	#[serde(default)]
+	pub indexes: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessStatus {
+	Active,
+	Errored,
+	Completed,
+	Failed,
+}
+
+impl ProcessStatus {
+	pub fn is_final(&self) -> bool {
+		matches!(self, ProcessStatus::Completed | ProcessStatus::Failed)
+	}
+}
+
+/// Written once when a guest reports that its workflow is final.
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct CompletionRecord {
+	pub version: u64,
+	pub completed_at: u64,
+	pub completion: Completion,
+}
+
+/// An `Operation::Event` emitted by a write, kept in the outbox until a dispatcher acknowledges
+/// it and then moved to the delivered keyspace with `delivered_at` set.
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct OutboxEntry {
+	pub id: String,
+	pub wasm: String,
+	pub process_id: String,
+	pub version: u64,
+	pub event: String,
+	pub created_at: u64,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub delivered_at: Option<u64>,
+}
+
+/// Result of a create or update stored under its idempotency key, returned to retries of the
+/// same request instead of running the guest again.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IdempotencyRecord {
+	pub stored_at: u64,
+	pub fingerprint: String,
+	pub process_id: String,
+	pub metadata: ProcessMetadata,
+	pub state: String,
+	pub operations: Vec<Operation>,
+	pub completion: Option<Completion>,
+	#[serde(default)]
+	pub event_status: EventStatus,
+	/// Status of each event of a batch, in order.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub event_statuses: Vec<EventStatus>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct ProcessError {
+	pub message: String,
+	pub occurred_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct HistoryEntry {
+	pub version: u64,
+	pub input: HistoryInput,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub enum HistoryInput {
+	Initialization {
+		parameter: String,
+	},
+	Event {
+		event: String,
+		/// Events recorded before the status was reported count as consumed.
+		#[serde(default)]
+		status: EventStatus,
+	},
+	/// A batch applied as a single transition.
+	Events {
+		events: Vec<String>,
+		statuses: Vec<EventStatus>,
+	},
+}
+
+/// Full state of a process at `version`, from which a replay can start instead of the
+/// initialization.
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct SnapshotEntry {
+	pub version: u64,
+	pub taken_at: u64,
+	pub state: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct ArchivedProcess {
+	pub archived_at: u64,
+	pub record: ProcessRecord,
+	pub metadata: ProcessMetadata,
+	pub completion: Option<CompletionRecord>,
+	pub history: Vec<HistoryEntry>,
+	#[serde(default)]
+	pub snapshots: Vec<SnapshotEntry>,
+}
+
+/// One line of a JSONL export, holding everything stored for a live process.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct ExportedProcess {
+	pub wasm: String,
+	pub process_id: String,
+	pub record: ProcessRecord,
+	pub metadata: ProcessMetadata,
+	pub completion: Option<CompletionRecord>,
+	pub history: Vec<HistoryEntry>,
+	#[serde(default)]
+	pub snapshots: Vec<SnapshotEntry>,
+}
+
+impl ExportedProcess {
+	/// Checks that the parts of the export are consistent with each other before they are stored.
+	pub fn validate(&self) -> anyhow::Result<()> {
+		if self.wasm.is_empty() || self.wasm.contains("::") {
+			return Err(HostError::bad_input("invalid_id", format!("{:?}", self.wasm)).into());
+		}
+		validate_process_id(self.process_id.as_str())?;
+		if self.record.version != self.metadata.version {
+			return Err(HostError::bad_input(
+				"version_mismatch",
+				"record and metadata versions differ",
+			)
+			.into());
+		}
+		serde_json::from_str::<serde_json::Value>(self.record.state.as_str())
+			.map_err(|error| HostError::bad_input("invalid_state", error))?;
+		let mut previous = 0;
+		for entry in self.history.iter() {
+			if entry.version <= previous || entry.version > self.record.version {
+				return Err(HostError::bad_input("invalid_history_version", entry.version).into());
+			}
+			previous = entry.version;
+		}
+		let mut previous = 0;
+		for snapshot in self.snapshots.iter() {
+			if snapshot.version <= previous || snapshot.version > self.record.version {
+				return Err(
+					HostError::bad_input("invalid_snapshot_version", snapshot.version).into(),
+				);
+			}
+			previous = snapshot.version;
+		}
+		if self.completion.is_some() != self.metadata.status.is_final() {
+			return Err(HostError::bad_input(
+				"completion_mismatch",
+				"completion does not match the status",
+			)
+			.into());
+		}
+		if self
+			.completion
+			.as_ref()
+			.is_some_and(|completion| completion.version > self.record.version)
+		{
+			return Err(HostError::bad_input(
+				"invalid_completion_version",
+				"completion is newer than the record",
+			)
+			.into());
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::ops::Bound;
+
+use sled::transaction::{ConflictableTransactionError, TransactionError, Transactional};
+use sled::{Db, Tree};
+
+use crate::db::process_store::{Keyspace, ProcessStore, Write};
+
+pub struct SledStore {
+	db: Db,
+	slots: HashMap<&'static str, usize>,
+	trees: Vec<Tree>,
+}
+
+impl SledStore {
+	pub fn open(path: &str) -> anyhow::Result<SledStore> {
+		SledStore::from_db(sled::open(path)?)
+	}
+
+	#[cfg(test)]
+	pub fn temporary() -> anyhow::Result<SledStore> {
+		SledStore::from_db(sled::Config::new().temporary(true).open()?)
+	}
+
+	fn from_db(db: Db) -> anyhow::Result<SledStore> {
+		let mut slots = HashMap::new();
+		let mut trees = Vec::new();
+		for keyspace in Keyspace::ALL {
+			slots.insert(keyspace.name(), trees.len());
+			trees.push(db.open_tree(keyspace.name())?);
+		}
+		let store = SledStore { db, slots, trees };
+		let count = store.migrate_default_tree()?;
+		if count > 0 {
+			println!("default_tree_migrated: {}", count);
+		}
+		Ok(store)
+	}
+
+	/// Moves the entries written before keyspaces existed, which all hold process state, from the
+	/// default tree into the state tree. Fails if a key holds a different value in both.
+	fn migrate_default_tree(&self) -> anyhow::Result<usize> {
+		let mut count = 0;
+		for entry in self.db.iter() {
+			let (key, value) = entry?;
+			let key = String::from_utf8(key.to_vec())?;
+			match self.get(Keyspace::State, key.as_str())? {
+				Some(current) if current != value.as_ref() => {
+					return Err(anyhow::Error::msg(format!("legacy_key_conflict: {}", key)));
+				}
+				_ => self.insert(Keyspace::State, key.as_str(), value.as_ref())?,
+			}
+			self.db.remove(key.as_str())?;
+			count += 1;
+		}
+		Ok(count)
+	}
+
+	/// Position of the tree of `keyspace` in `trees`, independent of the enum declaration order.
+	fn slot(&self, keyspace: Keyspace) -> usize {
+		self.slots[keyspace.name()]
+	}
+
+	fn tree(&self, keyspace: Keyspace) -> &Tree {
+		&self.trees[self.slot(keyspace)]
+	}
+}
+
+impl ProcessStore for SledStore {
+	fn get(&self, keyspace: Keyspace, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+		Ok(self.tree(keyspace).get(key)?.map(|value| value.to_vec()))
+	}
+
+	fn insert(&self, keyspace: Keyspace, key: &str, value: &[u8]) -> anyhow::Result<()> {
+		self.tree(keyspace).insert(key, value)?;
+		Ok(())
+	}
+
+	fn delete(&self, keyspace: Keyspace, key: &str) -> anyhow::Result<()> {
+		self.tree(keyspace).remove(key)?;
+		Ok(())
+	}
+
+	fn compare_and_swap(
+		&self,
+		keyspace: Keyspace,
+		key: &str,
+		expected: Option<&[u8]>,
+		new: Option<&[u8]>,
+		writes: &[Write],
+	) -> anyhow::Result<bool> {
+		let result = self.trees.as_slice().transaction(|trees| {
+			let tree = &trees[self.slot(keyspace)];
+			if tree.get(key)?.as_deref() != expected {
+				return Ok(false);
+			}
+			match new {
+				Some(value) => tree.insert(key, value)?,
+				None => tree.remove(key)?,
+			};
+			for write in writes {
+				match write {
+					Write::Insert {
+						keyspace,
+						key,
+						value,
+					} => trees[self.slot(*keyspace)].insert(key.as_str(), value.as_slice())?,
+					Write::Delete { keyspace, key } => {
+						trees[self.slot(*keyspace)].remove(key.as_str())?
+					}
+				};
+			}
+			Ok::<_, ConflictableTransactionError<()>>(true)
+		});
+		match result {
+			Ok(swapped) => Ok(swapped),
+			Err(TransactionError::Storage(error)) => Err(error.into()),
+			Err(TransactionError::Abort(())) => Ok(false),
+		}
+	}
+
+	fn scan(
+		&self,
+		keyspace: Keyspace,
+		prefix: &str,
+		after: Option<&str>,
+		limit: usize,
+	) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+		let start = match after {
+			Some(after) if after >= prefix => Bound::Excluded(after.as_bytes()),
+			_ => Bound::Included(prefix.as_bytes()),
+		};
+		let mut entries = Vec::new();
+		for entry in self
+			.tree(keyspace)
+			.range::<&[u8], _>((start, Bound::Unbounded))
+			.take(limit)
+		{
+			let (key, value) = entry?;
+			if !key.starts_with(prefix.as_bytes()) {
+				break;
+			}
+			entries.push((String::from_utf8(key.to_vec())?, value.to_vec()));
+		}
+		Ok(entries)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_migrate_default_tree() {
+		let db = sled::Config::new().temporary(true).open().unwrap();
+		db.insert("a.wasm::p", b"[1]".as_slice()).unwrap();
+		let store = SledStore::from_db(db.clone()).unwrap();
+		assert_eq!(
+			store.get(Keyspace::State, "a.wasm::p").unwrap(),
+			Some(b"[1]".to_vec())
+		);
+		assert!(db.is_empty());
+
+		db.insert("a.wasm::p", b"[2]".as_slice()).unwrap();
+		assert!(SledStore::from_db(db).is_err());
+	}
+}
@@ -0,0 +1,143 @@
+use std::sync::Mutex;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::db::process_store::{Keyspace, ProcessStore, Write};
+
+pub struct SqliteStore {
+	connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+	pub fn open(path: &str) -> anyhow::Result<SqliteStore> {
+		let connection = Connection::open(path)?;
+		connection.execute_batch(
+			"CREATE TABLE IF NOT EXISTS entries (
+				keyspace TEXT NOT NULL,
+				key TEXT NOT NULL,
+				value BLOB NOT NULL,
+				PRIMARY KEY (keyspace, key)
+			)",
+		)?;
+		Ok(SqliteStore {
+			connection: Mutex::new(connection),
+		})
+	}
+}
+
+fn get(
+	connection: &Connection,
+	keyspace: Keyspace,
+	key: &str,
+) -> rusqlite::Result<Option<Vec<u8>>> {
+	connection
+		.query_row(
+			"SELECT value FROM entries WHERE keyspace = ?1 AND key = ?2",
+			params![keyspace.name(), key],
+			|row| row.get(0),
+		)
+		.optional()
+}
+
+fn insert(
+	connection: &Connection,
+	keyspace: Keyspace,
+	key: &str,
+	value: &[u8],
+) -> rusqlite::Result<()> {
+	connection.execute(
+		"INSERT OR REPLACE INTO entries (keyspace, key, value) VALUES (?1, ?2, ?3)",
+		params![keyspace.name(), key, value],
+	)?;
+	Ok(())
+}
+
+fn delete(connection: &Connection, keyspace: Keyspace, key: &str) -> rusqlite::Result<()> {
+	connection.execute(
+		"DELETE FROM entries WHERE keyspace = ?1 AND key = ?2",
+		params![keyspace.name(), key],
+	)?;
+	Ok(())
+}
+
+impl ProcessStore for SqliteStore {
+	fn get(&self, keyspace: Keyspace, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+		Ok(get(&self.connection.lock().unwrap(), keyspace, key)?)
+	}
+
+	fn insert(&self, keyspace: Keyspace, key: &str, value: &[u8]) -> anyhow::Result<()> {
+		Ok(insert(
+			&self.connection.lock().unwrap(),
+			keyspace,
+			key,
+			value,
+		)?)
+	}
+
+	fn delete(&self, keyspace: Keyspace, key: &str) -> anyhow::Result<()> {
+		Ok(delete(&self.connection.lock().unwrap(), keyspace, key)?)
+	}
+
+	fn compare_and_swap(
+		&self,
+		keyspace: Keyspace,
+		key: &str,
+		expected: Option<&[u8]>,
+		new: Option<&[u8]>,
+		writes: &[Write],
+	) -> anyhow::Result<bool> {
+		let mut connection = self.connection.lock().unwrap();
+		let transaction = connection.transaction()?;
+		if get(&transaction, keyspace, key)?.as_deref() != expected {
+			return Ok(false);
+		}
+		match new {
+			Some(value) => insert(&transaction, keyspace, key, value)?,
+			None => delete(&transaction, keyspace, key)?,
+		}
+		for write in writes {
+			match write {
+				Write::Insert {
+					keyspace,
+					key,
+					value,
+				} => insert(&transaction, *keyspace, key, value)?,
+				Write::Delete { keyspace, key } => delete(&transaction, *keyspace, key)?,
+			}
+		}
+		transaction.commit()?;
+		Ok(true)
+	}
+
+	fn scan(
+		&self,
+		keyspace: Keyspace,
+		prefix: &str,
+		after: Option<&str>,
+		limit: usize,
+	) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+		let connection = self.connection.lock().unwrap();
+		let (start, inclusive) = match after {
+			Some(after) if after >= prefix => (after, false),
+			_ => (prefix, true),
+		};
+		let mut statement = connection.prepare(
+			"SELECT key, value FROM entries
+			WHERE keyspace = ?1 AND (key > ?2 OR (?3 AND key = ?2))
+			ORDER BY key",
+		)?;
+		let mut rows = statement.query(params![keyspace.name(), start, inclusive])?;
+		let mut entries = Vec::new();
+		while let Some(row) = rows.next()? {
+			if entries.len() >= limit {
+				break;
+			}
+			let key: String = row.get(0)?;
+			if !key.starts_with(prefix) {
+				break;
+			}
+			entries.push((key, row.get(1)?));
+		}
+		Ok(entries)
+	}
+}
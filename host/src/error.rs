@@ -0,0 +1,181 @@
+use std::fmt::{Display, Formatter};
+
+use axum::http::StatusCode;
+use serde::Serialize;
+use serde_json::Value;
+
+use common::GuestError;
+
+/// Failures surfaced to clients. Every variant carries a machine-readable code; guest errors
+/// bring their own and the other variants that wrap a message from elsewhere use a fixed one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostError {
+	NotFound(&'static str),
+	BadInput(&'static str, String),
+	/// The request does not match the JSON Schema the module publishes.
+	SchemaViolation(Vec<Violation>),
+	/// The guest rejected the request with a `Response::Error`.
+	Guest(GuestError),
+	/// No state accepted an event of a request that asked for dropped events to fail. The
+	/// guest only reports the drop; failing the request is the host's decision.
+	EventDropped(String),
+	/// The guest could not be instantiated, trapped or returned something unreadable.
+	Trap(String),
+	LimitExceeded(&'static str),
+	Conflict(&'static str),
+	Storage(String),
+}
+
+/// One way a value fails its schema. `pointer` is the JSON pointer of the offending value.
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct Violation {
+	pub pointer: String,
+	pub message: String,
+}
+
+impl HostError {
+	pub fn bad_input(code: &'static str, message: impl Display) -> HostError {
+		HostError::BadInput(code, message.to_string())
+	}
+
+	pub fn trap(error: anyhow::Error) -> HostError {
+		HostError::Trap(error.to_string())
+	}
+
+	/// Maps a guest `Response::Error` to the variant it stands for. Running out of update
+	/// iterations is reported by the guest executor but is a limit, not a guest decision. A
+	/// state reporting the same code is still a guest error.
+	pub fn from_guest(error: GuestError) -> HostError {
+		match error.is_executor("update_limit_exceeded") {
+			true => HostError::LimitExceeded("update_limit_exceeded"),
+			false => HostError::Guest(error),
+		}
+	}
+
+	/// Recovers the `HostError` inside `error`. Anything else comes from the store or from
+	/// (de)serializing stored values and is reported as a storage failure.
+	pub fn from_anyhow(error: anyhow::Error) -> HostError {
+		match error.downcast::<HostError>() {
+			Ok(error) => error,
+			Err(error) => HostError::Storage(error.to_string()),
+		}
+	}
+
+	pub fn kind(&self) -> &'static str {
+		match self {
+			HostError::NotFound(_) => "not_found",
+			HostError::BadInput(..)
+			| HostError::SchemaViolation(_)
+			| HostError::EventDropped(_) => "bad_input",
+			HostError::Guest(_) => "guest",
+			HostError::Trap(_) => "trap",
+			HostError::LimitExceeded(_) => "limit_exceeded",
+			HostError::Conflict(_) => "conflict",
+			HostError::Storage(_) => "storage",
+		}
+	}
+
+	pub fn code(&self) -> &str {
+		match self {
+			HostError::NotFound(code)
+			| HostError::BadInput(code, _)
+			| HostError::LimitExceeded(code)
+			| HostError::Conflict(code) => code,
+			HostError::SchemaViolation(_) => "schema_violation",
+			HostError::EventDropped(_) => "event_dropped",
+			HostError::Guest(error) => error.code.as_str(),
+			HostError::Trap(_) => "guest_trap",
+			HostError::Storage(_) => "storage_error",
+		}
+	}
+
+	/// Structured context: the document a guest attached to its error, or the list of schema
+	/// violations.
+	pub fn details(&self) -> Option<Value> {
+		match self {
+			HostError::Guest(error) => error.details.clone(),
+			HostError::SchemaViolation(violations) => serde_json::to_value(violations).ok(),
+			_ => None,
+		}
+	}
+
+	pub fn retryable(&self) -> bool {
+		match self {
+			HostError::Guest(error) => error.retryable,
+			_ => false,
+		}
+	}
+
+	pub fn status_code(&self) -> StatusCode {
+		match self {
+			HostError::NotFound(_) => StatusCode::NOT_FOUND,
+			HostError::BadInput(..) | HostError::SchemaViolation(_) => StatusCode::BAD_REQUEST,
+			HostError::Guest(_) | HostError::EventDropped(_) | HostError::LimitExceeded(_) => {
+				StatusCode::UNPROCESSABLE_ENTITY
+			}
+			HostError::Conflict(_) => StatusCode::CONFLICT,
+			HostError::Trap(_) | HostError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+		}
+	}
+}
+
+/// Prints the code alone where it says everything. Callers that need to tell errors apart
+/// downcast to [`HostError`] and match the variant instead of comparing this text.
+impl Display for HostError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			HostError::NotFound(code)
+			| HostError::LimitExceeded(code)
+			| HostError::Conflict(code) => {
+				write!(f, "{}", code)
+			}
+			HostError::BadInput(code, message) => write!(f, "{}: {}", code, message),
+			HostError::EventDropped(message) => write!(f, "event_dropped: {}", message),
+			HostError::SchemaViolation(violations) => {
+				write!(f, "schema_violation")?;
+				for violation in violations {
+					write!(f, "; {}: {}", violation.pointer, violation.message)?;
+				}
+				Ok(())
+			}
+			HostError::Guest(error) => write!(f, "{}", error),
+			HostError::Trap(message) | HostError::Storage(message) => {
+				write!(f, "{}", message)
+			}
+		}
+	}
+}
+
+impl std::error::Error for HostError {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_from_anyhow() {
+		let error = anyhow::Error::from(HostError::Conflict("version_conflict"));
+		assert_eq!(error.to_string(), "version_conflict");
+		let error = HostError::from_anyhow(error);
+		assert_eq!(error.status_code(), StatusCode::CONFLICT);
+		assert_eq!(error.code(), "version_conflict");
+
+		let error = HostError::from_anyhow(anyhow::Error::msg("io error"));
+		assert_eq!(error, HostError::Storage("io error".to_string()));
+		assert_eq!(error.kind(), "storage");
+
+		let error =
+			HostError::from_guest(GuestError::executor("update_limit_exceeded", "too many"));
+		assert_eq!(error.kind(), "limit_exceeded");
+		let error = HostError::from_guest(GuestError::new("update_limit_exceeded", "faked"));
+		assert_eq!(error.kind(), "guest");
+		let mut guest_error = GuestError::new("insufficient_funds", "balance is 3");
+		guest_error.details = Some(serde_json::json!({ "balance": 3 }));
+		let error = HostError::from_guest(guest_error);
+		assert_eq!(error.kind(), "guest");
+		assert_eq!(error.code(), "insufficient_funds");
+		assert_eq!(error.details(), Some(serde_json::json!({ "balance": 3 })));
+		assert!(!error.retryable());
+		assert_eq!(error.to_string(), "insufficient_funds: balance is 3");
+	}
+}
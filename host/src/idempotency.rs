@@ -0,0 +1,33 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::AppState;
+
+const PAGE_SIZE: usize = 100;
+
+/// Periodically removes stored idempotency results that are older than the configured window.
+pub async fn run(app_state: Arc<AppState>, interval_secs: u64) {
+	let mut interval = tokio::time::interval(Duration::from_secs(interval_secs.max(1)));
+	loop {
+		interval.tick().await;
+		match expire_idempotency(&app_state) {
+			Ok(count) => println!("idempotency_expiry: {} results", count),
+			Err(error) => println!("idempotency_expiry_failed: {}", error),
+		}
+	}
+}
+
+fn expire_idempotency(app_state: &AppState) -> anyhow::Result<usize> {
+	let mut cursor: Option<String> = None;
+	let mut count = 0;
+	loop {
+		let (last, removed) = app_state
+			.db_handler
+			.expire_idempotency(cursor.as_deref(), PAGE_SIZE)?;
+		count += removed;
+		match last {
+			Some(last) => cursor = Some(last),
+			None => return Ok(count),
+		}
+	}
+}
@@ -0,0 +1,3 @@
+pub use process_lock::{LockMetrics, ProcessLocks};
+
+mod process_lock;
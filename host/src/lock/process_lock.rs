@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+use tokio::time::Instant;
+
+use crate::db::form_key;
+
+#[derive(Default)]
+pub struct ProcessLocks {
+	locks: Mutex<HashMap<String, Arc<AsyncMutex<()>>>>,
+	acquisitions: AtomicU64,
+	contended_acquisitions: AtomicU64,
+	waiting: AtomicU64,
+	total_wait_micros: AtomicU64,
+	max_wait_micros: AtomicU64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LockMetrics {
+	active_locks: usize,
+	acquisitions: u64,
+	contended_acquisitions: u64,
+	waiting: u64,
+	total_wait_micros: u64,
+	max_wait_micros: u64,
+}
+
+pub struct ProcessGuard<'a> {
+	locks: &'a ProcessLocks,
+	key: String,
+	guard: OwnedMutexGuard<()>,
+}
+
+/// Counts a waiter until it is dropped, which also happens when the waiting request is cancelled.
+struct Waiting<'a>(&'a AtomicU64);
+
+impl<'a> Waiting<'a> {
+	fn new(counter: &'a AtomicU64) -> Waiting<'a> {
+		counter.fetch_add(1, Ordering::Relaxed);
+		Waiting(counter)
+	}
+}
+
+impl Drop for Waiting<'_> {
+	fn drop(&mut self) {
+		self.0.fetch_sub(1, Ordering::Relaxed);
+	}
+}
+
+impl ProcessLocks {
+	pub fn new() -> ProcessLocks {
+		ProcessLocks::default()
+	}
+
+	pub async fn lock(&self, wasm: &str, process_id: &str) -> ProcessGuard<'_> {
+		let key = form_key(wasm, process_id);
+		let mutex = self
+			.locks
+			.lock()
+			.unwrap()
+			.entry(key.clone())
+			.or_default()
+			.clone();
+		self.acquisitions.fetch_add(1, Ordering::Relaxed);
+		let guard = match mutex.clone().try_lock_owned() {
+			Ok(guard) => guard,
+			Err(_) => {
+				self.contended_acquisitions.fetch_add(1, Ordering::Relaxed);
+				let waiting = Waiting::new(&self.waiting);
+				let now = Instant::now();
+				let guard = mutex.lock_owned().await;
+				let wait_micros = now.elapsed().as_micros() as u64;
+				drop(waiting);
+				self.total_wait_micros
+					.fetch_add(wait_micros, Ordering::Relaxed);
+				self.max_wait_micros
+					.fetch_max(wait_micros, Ordering::Relaxed);
+				guard
+			}
+		};
+		ProcessGuard {
+			locks: self,
+			key,
+			guard,
+		}
+	}
+
+	pub fn metrics(&self) -> LockMetrics {
+		LockMetrics {
+			active_locks: self.locks.lock().unwrap().len(),
+			acquisitions: self.acquisitions.load(Ordering::Relaxed),
+			contended_acquisitions: self.contended_acquisitions.load(Ordering::Relaxed),
+			waiting: self.waiting.load(Ordering::Relaxed),
+			total_wait_micros: self.total_wait_micros.load(Ordering::Relaxed),
+			max_wait_micros: self.max_wait_micros.load(Ordering::Relaxed),
+		}
+	}
+}
+
+impl Drop for ProcessGuard<'_> {
+	fn drop(&mut self) {
+		// The map and this guard are the only owners when nobody else is queued,
+		// so the entry can be removed without racing a new waiter.
+		let mut locks = self.locks.locks.lock().unwrap();
+		if Arc::strong_count(OwnedMutexGuard::mutex(&self.guard)) == 2 {
+			locks.remove(&self.key);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use std::time::Duration;
+
+	use super::*;
+
+	#[tokio::test]
+	async fn test_same_process_is_serialized() {
+		let locks = Arc::new(ProcessLocks::new());
+		let order = Arc::new(Mutex::new(Vec::new()));
+
+		let guard = locks.lock("a.wasm", "p").await;
+		let task = {
+			let locks = locks.clone();
+			let order = order.clone();
+			tokio::spawn(async move {
+				let _guard = locks.lock("a.wasm", "p").await;
+				order.lock().unwrap().push(2);
+			})
+		};
+		tokio::time::sleep(Duration::from_millis(20)).await;
+		order.lock().unwrap().push(1);
+		drop(guard);
+		task.await.unwrap();
+
+		assert_eq!(*order.lock().unwrap(), vec![1, 2]);
+		let metrics = locks.metrics();
+		assert_eq!(metrics.acquisitions, 2);
+		assert_eq!(metrics.contended_acquisitions, 1);
+		assert_eq!(metrics.active_locks, 0);
+	}
+
+	#[tokio::test]
+	async fn test_cancelled_waiter_is_not_counted() {
+		let locks = Arc::new(ProcessLocks::new());
+		let guard = locks.lock("a.wasm", "p").await;
+		let task = {
+			let locks = locks.clone();
+			tokio::spawn(async move {
+				let _guard = locks.lock("a.wasm", "p").await;
+			})
+		};
+		tokio::time::sleep(Duration::from_millis(20)).await;
+		assert_eq!(locks.metrics().waiting, 1);
+		task.abort();
+		assert!(task.await.unwrap_err().is_cancelled());
+		assert_eq!(locks.metrics().waiting, 0);
+		drop(guard);
+		assert_eq!(locks.metrics().active_locks, 0);
+	}
+
+	#[tokio::test]
+	async fn test_different_processes_run_in_parallel() {
+		let locks = ProcessLocks::new();
+		let _first = locks.lock("a.wasm", "p").await;
+		let _second = locks.lock("a.wasm", "q").await;
+		let metrics = locks.metrics();
+		assert_eq!(metrics.contended_acquisitions, 0);
+		assert_eq!(metrics.active_locks, 2);
+	}
+}
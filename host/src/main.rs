@@ -2,17 +2,31 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 
 use axum::routing::{get, post};
-use axum::{Router, Server, ServiceExt};
-use wasmtime::{Engine, Module};
+use axum::{Router, Server};
+use wasmtime::Engine;
 
-use common::{Request, Response};
-
-use crate::db::DbHandler;
-use crate::route::{create_handler, update_handler};
-use crate::wasm::ModuleCache;
+use crate::config::Config;
+use crate::db::{Cipher, DbHandler};
+use crate::lock::ProcessLocks;
+use crate::route::{
+	acknowledge_handler, archive_handler, archived_handler, batch_handler, completion_handler,
+	create_handler, delete_handler, history_handler, list_handler, lock_metrics_handler,
+	outbox_handler, process_handler, query_handler, replay_handler, schema_handler,
+	simulate_handler, update_handler,
+};
+use crate::wasm::{ModuleCache, SchemaCache};
 
+mod compaction;
+mod config;
 mod db;
+mod error;
+mod idempotency;
+mod lock;
+mod outbox;
+mod reencryption;
+mod retention;
 mod route;
+mod transfer;
 mod wasm;
 // fn main() {
 // 	let args: Vec<String> = std::env::args().collect();
@@ -66,22 +80,108 @@ mod wasm;
 pub struct AppState {
 	engine: Engine,
 	module_cache: ModuleCache,
+	schema_cache: SchemaCache,
 	db_handler: DbHandler,
+	process_locks: ProcessLocks,
+}
+
+impl AppState {
+	/// State with an in-memory store and one module, `a.wasm`, that answers every request with
+	/// `response` and does not know `Request::Describe`, for tests.
+	#[cfg(test)]
+	fn temporary(response: &common::Response) -> AppState {
+		let engine = Engine::default();
+		// Answers Describe the way a module built before it existed does.
+		let guest = wasm::canned_guest(
+			"unknown variant `Describe`",
+			serde_json::to_string(response).unwrap().as_str(),
+		);
+		let module_cache = ModuleCache::with_module(&engine, "a.wasm", guest.as_str()).unwrap();
+		AppState {
+			engine,
+			module_cache,
+			schema_cache: SchemaCache::new(),
+			db_handler: DbHandler::temporary(),
+			process_locks: ProcessLocks::new(),
+		}
+	}
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+	let config = Config::load()?;
+	let mut db_handler = DbHandler::open(&config.store)?
+		.with_snapshots(config.snapshot)
+		.with_idempotency_window(config.idempotency_window_secs);
+	if let Some(encryption) = &config.encryption {
+		db_handler = db_handler.with_encryption(Cipher::load(encryption.key_file.as_str())?);
+	}
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	if !args.is_empty() {
+		return transfer::run(&db_handler, args.as_slice());
+	}
 	let engine = Engine::default();
-	let module_cache = ModuleCache::load_directory(&engine, "wasm-files")?;
-	let db_handler = DbHandler::load_directory("process-db")?;
+	let module_cache = ModuleCache::load_directory(&engine, config.wasm_directory.as_str())?;
 	let state = Arc::new(AppState {
 		engine,
 		module_cache,
+		schema_cache: SchemaCache::new(),
 		db_handler,
+		process_locks: ProcessLocks::new(),
 	});
+	tokio::spawn(retention::run(
+		state.clone(),
+		config.retention,
+		config.retention_interval_secs,
+	));
+	tokio::spawn(idempotency::run(
+		state.clone(),
+		config.retention_interval_secs,
+	));
+	if let Some(encryption) = &config.encryption {
+		tokio::spawn(reencryption::run(
+			state.clone(),
+			encryption
+				.reencryption_interval_secs
+				.unwrap_or(config.retention_interval_secs),
+		));
+	}
+	if let Some(dispatcher) = config.dispatcher {
+		tokio::spawn(outbox::run(state.clone(), dispatcher));
+	}
+	if let Some(compact_after_days) = config.snapshot.compact_after_days {
+		tokio::spawn(compaction::run(
+			state.clone(),
+			compact_after_days,
+			config.retention_interval_secs,
+		));
+	}
 	let app = Router::new()
 		.route("/create", post(create_handler))
 		.route("/update", post(update_handler))
+		.route("/simulate", post(simulate_handler))
+		.route("/batch", post(batch_handler))
+		.route("/modules/:wasm/processes", get(list_handler))
+		.route("/modules/:wasm/schema", get(schema_handler))
+		.route(
+			"/processes/:wasm/:process_id",
+			get(process_handler).delete(delete_handler),
+		)
+		.route("/processes/:wasm/:process_id/history", get(history_handler))
+		.route("/processes/:wasm/:process_id/replay", get(replay_handler))
+		.route("/processes/:wasm/:process_id/query", post(query_handler))
+		.route(
+			"/processes/:wasm/:process_id/completion",
+			get(completion_handler),
+		)
+		.route(
+			"/processes/:wasm/:process_id/archive",
+			post(archive_handler),
+		)
+		.route("/archive/:wasm/:process_id", get(archived_handler))
+		.route("/outbox", get(outbox_handler))
+		.route("/outbox/ack", post(acknowledge_handler))
+		.route("/metrics/locks", get(lock_metrics_handler))
 		.with_state(state);
 	let address = SocketAddr::from(([127, 0, 0, 1], 3000));
 	Server::bind(&address)
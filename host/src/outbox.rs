@@ -0,0 +1,135 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::header::CONTENT_TYPE;
+use axum::http::Request;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client};
+
+use crate::config::DispatcherConfig;
+use crate::db::DbHandler;
+use crate::AppState;
+
+const PAGE_SIZE: usize = 100;
+
+/// Periodically posts pending outbox entries to the configured endpoint and acknowledges every
+/// entry it accepts, so each emitted event is delivered at least once.
+pub async fn run(app_state: Arc<AppState>, config: DispatcherConfig) {
+	let client = Client::new();
+	let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs.max(1)));
+	loop {
+		interval.tick().await;
+		match dispatch(&client, &app_state.db_handler, config.url.as_str()).await {
+			Ok(0) => {}
+			Ok(count) => println!("outbox_dispatch: {} events", count),
+			Err(error) => println!("outbox_dispatch_failed: {}", error),
+		}
+	}
+}
+
+/// Delivers pending entries in outbox order. Delivery stops at the first failure, so the events
+/// of a process never overtake each other; the rest is retried on the next run.
+async fn dispatch(
+	client: &Client<HttpConnector>,
+	db_handler: &DbHandler,
+	url: &str,
+) -> anyhow::Result<usize> {
+	let mut count = 0;
+	loop {
+		// Acknowledged entries leave the outbox, so every page starts from the beginning.
+		let entries = db_handler.pending_operations(None, PAGE_SIZE)?;
+		for entry in entries.iter() {
+			let request = Request::post(url)
+				.header(CONTENT_TYPE, "application/json")
+				.body(Body::from(serde_json::to_vec(entry)?))?;
+			let response = client.request(request).await?;
+			if !response.status().is_success() {
+				return Err(anyhow::Error::msg(format!(
+					"delivery_rejected: {} {}",
+					entry.id,
+					response.status()
+				)));
+			}
+			count += db_handler.acknowledge(std::slice::from_ref(&entry.id))?;
+		}
+		if entries.len() < PAGE_SIZE {
+			return Ok(count);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Mutex;
+
+	use axum::extract::State;
+	use axum::http::StatusCode;
+	use axum::routing::post;
+	use axum::{Json, Router, Server};
+	use serde_json::Value;
+
+	use common::Operation;
+
+	use super::*;
+	use crate::db::{transition, Transition};
+
+	type Received = Arc<Mutex<Vec<Value>>>;
+
+	async fn receive(State(received): State<Received>, Json(entry): Json<Value>) -> StatusCode {
+		let mut received = received.lock().unwrap();
+		received.push(entry);
+		match received.len() {
+			2 => StatusCode::SERVICE_UNAVAILABLE,
+			_ => StatusCode::OK,
+		}
+	}
+
+	#[tokio::test]
+	async fn test_dispatch() {
+		let db_handler = DbHandler::temporary();
+		let operations = [
+			Operation::Event("1".to_string()),
+			Operation::Event("2".to_string()),
+		];
+		db_handler
+			.insert(
+				"a.wasm",
+				"p",
+				"{}",
+				&Transition {
+					operations: &operations,
+					..transition("{}", "hash")
+				},
+			)
+			.unwrap();
+		let received = Received::default();
+		let app = Router::new()
+			.route("/events", post(receive))
+			.with_state(received.clone());
+		let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+		let url = format!("http://{}/events", server.local_addr());
+		tokio::spawn(server);
+
+		let client = Client::new();
+		let error = dispatch(&client, &db_handler, url.as_str())
+			.await
+			.unwrap_err();
+		assert!(error.to_string().starts_with("delivery_rejected"));
+		let pending = db_handler.pending_operations(None, 10).unwrap();
+		assert_eq!(pending.len(), 1);
+		assert_eq!(pending[0].event, "2");
+
+		assert_eq!(
+			dispatch(&client, &db_handler, url.as_str()).await.unwrap(),
+			1
+		);
+		assert!(db_handler.pending_operations(None, 10).unwrap().is_empty());
+		let events: Vec<String> = received
+			.lock()
+			.unwrap()
+			.iter()
+			.map(|entry| entry["event"].as_str().unwrap().to_string())
+			.collect();
+		assert_eq!(events, vec!["1", "2", "2"]);
+	}
+}
@@ -0,0 +1,32 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::AppState;
+
+const PAGE_SIZE: usize = 100;
+
+/// Periodically rewraps stored values with the active encryption key, so retired keys can be
+/// removed from the key file once a full pass has completed.
+pub async fn run(app_state: Arc<AppState>, interval_secs: u64) {
+	let mut interval = tokio::time::interval(Duration::from_secs(interval_secs.max(1)));
+	loop {
+		interval.tick().await;
+		match reencrypt(&app_state) {
+			Ok(count) => println!("reencryption: {} values", count),
+			Err(error) => println!("reencryption_failed: {}", error),
+		}
+	}
+}
+
+fn reencrypt(app_state: &AppState) -> anyhow::Result<usize> {
+	let mut cursor = None;
+	let mut count = 0;
+	loop {
+		let (next, rewritten) = app_state.db_handler.reencrypt(cursor, PAGE_SIZE)?;
+		count += rewritten;
+		match next {
+			Some(next) => cursor = Some(next),
+			None => return Ok(count),
+		}
+	}
+}
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::{RetentionAction, RetentionRule};
+use crate::db::unix_timestamp;
+use crate::error::HostError;
+use crate::AppState;
+
+const PAGE_SIZE: usize = 100;
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Periodically enforces the per-module retention rules until the host exits.
+pub async fn run(
+	app_state: Arc<AppState>,
+	rules: HashMap<String, RetentionRule>,
+	interval_secs: u64,
+) {
+	if rules.is_empty() {
+		return;
+	}
+	let mut interval = tokio::time::interval(Duration::from_secs(interval_secs.max(1)));
+	loop {
+		interval.tick().await;
+		for (wasm, rule) in rules.iter() {
+			match sweep(&app_state, wasm.as_str(), rule).await {
+				Ok(count) => println!("retention_sweep: {} {} processes", wasm, count),
+				Err(error) => println!("retention_sweep_failed: {} {}", wasm, error),
+			}
+		}
+	}
+}
+
+async fn sweep(app_state: &AppState, wasm: &str, rule: &RetentionRule) -> anyhow::Result<usize> {
+	let cutoff = unix_timestamp().saturating_sub(rule.after_days * SECONDS_PER_DAY);
+	let mut cursor: Option<String> = None;
+	let mut count = 0;
+	loop {
+		let page = app_state
+			.db_handler
+			.list(wasm, cursor.as_deref(), PAGE_SIZE)?;
+		cursor = page.last().map(|(process_id, _)| process_id.clone());
+		for (process_id, metadata) in page.iter() {
+			if metadata.updated_at > cutoff || (rule.completed_only && !metadata.status.is_final())
+			{
+				continue;
+			}
+			let _guard = app_state.process_locks.lock(wasm, process_id).await;
+			let version = Some(metadata.version);
+			let result = match rule.action {
+				RetentionAction::Delete => app_state.db_handler.delete(wasm, process_id, version),
+				RetentionAction::Archive => app_state.db_handler.archive(wasm, process_id, version),
+			};
+			// A process written or removed since the scan is no longer expired, so it is skipped.
+			match result {
+				Ok(()) => count += 1,
+				Err(error)
+					if matches!(
+						error.downcast_ref::<HostError>(),
+						Some(HostError::Conflict("version_conflict"))
+							| Some(HostError::NotFound("process_not_found"))
+					) => {}
+				Err(error) => return Err(error),
+			}
+		}
+		if page.len() < PAGE_SIZE {
+			return Ok(count);
+		}
+	}
+}
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{Path, Query, State};
+use serde::{Deserialize, Serialize};
+
+use crate::db::ArchivedProcess;
+use crate::route::{parse_query, HandlerResponse};
+use crate::AppState;
+
+#[derive(Deserialize)]
+pub struct ArchiveQuery {
+	expected_version: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct ArchiveResponse {
+	wasm: String,
+	process_id: String,
+}
+
+pub async fn archive_handler(
+	State(state): State<Arc<AppState>>,
+	Path((wasm, process_id)): Path<(String, String)>,
+	query: Result<Query<ArchiveQuery>, QueryRejection>,
+) -> HandlerResponse<ArchiveResponse> {
+	let query = match parse_query(query) {
+		Ok(query) => query,
+		Err(error) => return HandlerResponse::Error(error),
+	};
+	let _guard = state
+		.process_locks
+		.lock(wasm.as_str(), process_id.as_str())
+		.await;
+	let result = state
+		.db_handler
+		.archive(wasm.as_str(), process_id.as_str(), query.expected_version)
+		.map(|_| ArchiveResponse { wasm, process_id });
+	HandlerResponse::from_result(result)
+}
+
+pub async fn archived_handler(
+	State(state): State<Arc<AppState>>,
+	Path((wasm, process_id)): Path<(String, String)>,
+) -> HandlerResponse<ArchivedProcess> {
+	HandlerResponse::from_result(
+		state
+			.db_handler
+			.get_archive(wasm.as_str(), process_id.as_str()),
+	)
+}
@@ -0,0 +1,206 @@
+use std::sync::Arc;
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::State;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use wasmtime::Module;
+
+use common::{Completion, EventStatus, Operation, Request, Snapshot};
+
+use crate::db::{Idempotency, IdempotencyRecord, ProcessMetadata, Transition};
+use crate::error::{HostError, Violation};
+use crate::route::update::{load_state, record_failure, warnings, OnDropped};
+use crate::route::{check_idempotency, fingerprint, parse_body, HandlerResponse};
+use crate::wasm::Program;
+use crate::AppState;
+
+const MAX_EVENTS: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct BatchRequest {
+	wasm: String,
+	process_id: String,
+	events: Vec<Map<String, Value>>,
+	expected_version: Option<u64>,
+	idempotency_key: Option<String>,
+	#[serde(default)]
+	on_dropped: OnDropped,
+}
+
+#[derive(Serialize)]
+pub struct BatchResponse {
+	wasm: String,
+	process_id: String,
+	version: u64,
+	metadata: ProcessMetadata,
+	state: Map<String, Value>,
+	operations: Vec<Operation>,
+	completion: Option<Completion>,
+	event_statuses: Vec<EventStatus>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	warnings: Vec<&'static str>,
+	#[serde(skip_serializing_if = "std::ops::Not::not")]
+	replayed: bool,
+}
+
+impl BatchResponse {
+	fn replayed(
+		wasm: String,
+		record: IdempotencyRecord,
+		on_dropped: OnDropped,
+	) -> anyhow::Result<BatchResponse> {
+		Ok(BatchResponse {
+			warnings: warnings(record.event_statuses.as_slice(), on_dropped),
+			event_statuses: record.event_statuses,
+			wasm,
+			process_id: record.process_id,
+			version: record.metadata.version,
+			metadata: record.metadata,
+			state: serde_json::from_str(record.state.as_str())?,
+			operations: record.operations,
+			completion: record.completion,
+			replayed: true,
+		})
+	}
+}
+
+pub async fn batch_handler(
+	State(state): State<Arc<AppState>>,
+	request: Result<Json<BatchRequest>, JsonRejection>,
+) -> HandlerResponse<BatchResponse> {
+	let request = match parse_body(request) {
+		Ok(request) => request,
+		Err(error) => return HandlerResponse::Error(error),
+	};
+	let _guard = state
+		.process_locks
+		.lock(request.wasm.as_str(), request.process_id.as_str())
+		.await;
+	HandlerResponse::from_result(batch(request, &state))
+}
+
+/// Applies every event of `request` in one execution and stores the result as a single new
+/// version. Nothing is stored unless every event succeeds. A retry with the same idempotency
+/// key gets the stored result back, as for an update.
+fn batch(request: BatchRequest, app_state: &AppState) -> anyhow::Result<BatchResponse> {
+	if request.events.is_empty() {
+		return Err(HostError::bad_input("empty_batch", "events must not be empty").into());
+	}
+	if request.events.len() > MAX_EVENTS {
+		return Err(HostError::LimitExceeded("batch_too_large").into());
+	}
+	let fingerprint = fingerprint(&(&request.events, request.expected_version))?;
+	if let Some(key) = request.idempotency_key.as_deref() {
+		let record = app_state.db_handler.get_idempotency(
+			request.wasm.as_str(),
+			Some(request.process_id.as_str()),
+			key,
+		)?;
+		if let Some(record) = check_idempotency(record, fingerprint.as_str())? {
+			return BatchResponse::replayed(request.wasm, record, request.on_dropped);
+		}
+	}
+	let module = app_state
+		.module_cache
+		.get_module(request.wasm.as_str())
+		.ok_or(HostError::NotFound("module_not_found"))?;
+	if let Some(schemas) =
+		app_state
+			.schema_cache
+			.get(&app_state.engine, request.wasm.as_str(), module)?
+	{
+		let mut violations = Vec::new();
+		for (index, event) in request.events.iter().enumerate() {
+			if let Err(HostError::SchemaViolation(event_violations)) =
+				schemas.validate_event(&Value::Object(event.clone()))
+			{
+				violations.extend(event_violations.into_iter().map(|violation| Violation {
+					pointer: format!("/events/{}{}", index, violation.pointer),
+					message: violation.message,
+				}));
+			}
+		}
+		if !violations.is_empty() {
+			return Err(HostError::SchemaViolation(violations).into());
+		}
+	}
+	let (version, state) = load_state(
+		app_state,
+		request.wasm.as_str(),
+		request.process_id.as_str(),
+		request.expected_version,
+	)?;
+	let events = request
+		.events
+		.iter()
+		.map(serde_json::to_string)
+		.collect::<Result<Vec<String>, _>>()?;
+	let snapshot = match execute_events(app_state, module, state, events.clone()) {
+		Ok(snapshot) => snapshot,
+		Err(error) => {
+			record_failure(
+				app_state,
+				request.wasm.as_str(),
+				request.process_id.as_str(),
+				version,
+				&error,
+			);
+			return Err(error);
+		}
+	};
+	let dropped = snapshot
+		.event_statuses
+		.iter()
+		.position(|event_status| *event_status == EventStatus::Dropped);
+	if let (Some(index), OnDropped::Error) = (dropped, request.on_dropped) {
+		let message = format!("event {}: no state accepted the event", index);
+		return Err(HostError::EventDropped(message).into());
+	}
+	let module_hash = app_state
+		.module_cache
+		.get_module_hash(request.wasm.as_str())
+		.unwrap_or_default();
+	let metadata = app_state.db_handler.compare_and_swap_events(
+		request.wasm.as_str(),
+		request.process_id.as_str(),
+		version,
+		events.as_slice(),
+		snapshot.event_statuses.as_slice(),
+		&Transition {
+			state: snapshot.state.as_str(),
+			module_hash,
+			completion: snapshot.completion.as_ref(),
+			indexes: &snapshot.indexes,
+			operations: snapshot.operations.as_slice(),
+			event_status: snapshot.event_status,
+			idempotency: request.idempotency_key.as_deref().map(|key| Idempotency {
+				key,
+				fingerprint: fingerprint.as_str(),
+			}),
+		},
+	)?;
+	Ok(BatchResponse {
+		wasm: request.wasm,
+		process_id: request.process_id,
+		version: metadata.version,
+		metadata,
+		state: serde_json::from_str(snapshot.state.as_str())?,
+		operations: snapshot.operations,
+		completion: snapshot.completion,
+		warnings: warnings(snapshot.event_statuses.as_slice(), request.on_dropped),
+		event_statuses: snapshot.event_statuses,
+		replayed: false,
+	})
+}
+
+pub fn execute_events(
+	app_state: &AppState,
+	module: &Module,
+	state: String,
+	events: Vec<String>,
+) -> anyhow::Result<Snapshot> {
+	let mut program = Program::new(&app_state.engine, module)?;
+	program.execute_snapshot(&Request::Events { state, events })
+}
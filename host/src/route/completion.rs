@@ -0,0 +1,18 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+
+use crate::db::CompletionRecord;
+use crate::route::HandlerResponse;
+use crate::AppState;
+
+pub async fn completion_handler(
+	State(state): State<Arc<AppState>>,
+	Path((wasm, process_id)): Path<(String, String)>,
+) -> HandlerResponse<CompletionRecord> {
+	HandlerResponse::from_result(
+		state
+			.db_handler
+			.get_completion(wasm.as_str(), process_id.as_str()),
+	)
+}
@@ -1,65 +1,283 @@
-use std::os::linux::raw::stat;
 use std::sync::Arc;
 
+use axum::extract::rejection::JsonRejection;
 use axum::extract::State;
-use axum::routing::any;
 use axum::Json;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 use uuid::Uuid;
-use wasmtime::Engine;
 
-use common::{Operation, Request, Response};
+use common::{Completion, Operation, Request};
 
-use crate::db::DbHandler;
-use crate::route::HandlerResponse;
-use crate::wasm::{ModuleCache, Program};
+use crate::db::{
+	validate_process_id, HistoryInput, Idempotency, IdempotencyRecord, ProcessMetadata, Transition,
+};
+use crate::error::HostError;
+use crate::route::{check_idempotency, fingerprint, parse_body, HandlerResponse};
+use crate::wasm::Program;
 use crate::AppState;
 
 #[derive(Deserialize)]
 pub struct CreateRequest {
 	wasm: String,
 	parameter: Map<String, Value>,
+	idempotency_key: Option<String>,
+	process_id: Option<String>,
+	#[serde(default)]
+	on_conflict: OnConflict,
+}
+
+/// What a create with a client supplied `process_id` does if that process already exists.
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum OnConflict {
+	#[default]
+	Fail,
+	/// Returns the existing process, as long as it was created with the same parameter.
+	ReturnExisting,
 }
 
 #[derive(Serialize)]
 pub struct CreateResponse {
 	wasm: String,
 	process_id: String,
+	version: u64,
+	metadata: ProcessMetadata,
 	state: Map<String, Value>,
 	operations: Vec<Operation>,
+	completion: Option<Completion>,
+	#[serde(skip_serializing_if = "std::ops::Not::not")]
+	replayed: bool,
+}
+
+impl CreateResponse {
+	fn replayed(wasm: String, record: IdempotencyRecord) -> anyhow::Result<CreateResponse> {
+		Ok(CreateResponse {
+			wasm,
+			process_id: record.process_id,
+			version: record.metadata.version,
+			metadata: record.metadata,
+			state: serde_json::from_str(record.state.as_str())?,
+			operations: record.operations,
+			completion: record.completion,
+			replayed: true,
+		})
+	}
+
+	fn existing(
+		wasm: String,
+		process_id: String,
+		app_state: &AppState,
+	) -> anyhow::Result<CreateResponse> {
+		let record = app_state
+			.db_handler
+			.get(wasm.as_str(), process_id.as_str())?;
+		let metadata = app_state
+			.db_handler
+			.get_metadata(wasm.as_str(), process_id.as_str())?;
+		let completion = app_state
+			.db_handler
+			.get_completion(wasm.as_str(), process_id.as_str())
+			.ok()
+			.map(|record| record.completion);
+		Ok(CreateResponse {
+			wasm,
+			process_id,
+			version: record.version,
+			metadata,
+			state: serde_json::from_str(record.state.as_str())?,
+			operations: Vec::new(),
+			completion,
+			replayed: true,
+		})
+	}
 }
 
 pub async fn create_handler(
 	State(state): State<Arc<AppState>>,
-	request: Json<CreateRequest>,
-) -> Json<HandlerResponse<CreateResponse>> {
-	HandlerResponse::from_result(create(request.0, &state)).into()
+	request: Result<Json<CreateRequest>, JsonRejection>,
+) -> HandlerResponse<CreateResponse> {
+	let request = match parse_body(request) {
+		Ok(request) => request,
+		Err(error) => return HandlerResponse::Error(error),
+	};
+	// Creates with the same idempotency key or process id are serialized so only one of them
+	// runs the guest.
+	let _process_guard = match request.process_id.as_deref() {
+		Some(process_id) => Some(
+			state
+				.process_locks
+				.lock(request.wasm.as_str(), process_id)
+				.await,
+		),
+		None => None,
+	};
+	let _guard = match request.idempotency_key.as_deref() {
+		Some(key) => Some(
+			state
+				.process_locks
+				.lock(request.wasm.as_str(), format!("::{}", key).as_str())
+				.await,
+		),
+		None => None,
+	};
+	HandlerResponse::from_result(create(request, &state))
 }
 
 fn create(request: CreateRequest, app_state: &AppState) -> anyhow::Result<CreateResponse> {
+	let fingerprint = fingerprint(&(
+		&request.parameter,
+		request.process_id.as_deref(),
+		request.on_conflict,
+	))?;
+	if let Some(key) = request.idempotency_key.as_deref() {
+		let record = app_state
+			.db_handler
+			.get_idempotency(request.wasm.as_str(), None, key)?;
+		if let Some(record) = check_idempotency(record, fingerprint.as_str())? {
+			return CreateResponse::replayed(request.wasm, record);
+		}
+	}
+	let parameter = serde_json::to_string(&request.parameter)?;
+	if let Some(process_id) = request.process_id.as_deref() {
+		validate_process_id(process_id)?;
+		if exists(app_state, request.wasm.as_str(), process_id)? {
+			return match request.on_conflict {
+				OnConflict::ReturnExisting
+					if same_parameter(app_state, &request, parameter.as_str())? =>
+				{
+					CreateResponse::existing(request.wasm, process_id.to_string(), app_state)
+				}
+				_ => Err(HostError::Conflict("process_already_exists").into()),
+			};
+		}
+	}
 	let module = app_state
 		.module_cache
 		.get_module(request.wasm.as_str())
-		.ok_or(anyhow::Error::msg("module_not_found"))?;
-	let mut program = Program::new(&app_state.engine, &module)?;
-	let parameter = serde_json::to_string(&request.parameter)?;
-	let program_request = Request::Initialization { parameter };
-	let response = program.execute_request(&program_request)?;
-	let snapshot = match response {
-		Response::Error(e) => return Err(anyhow::Error::msg(e)),
-		Response::Snapshot(s) => s,
+		.ok_or(HostError::NotFound("module_not_found"))?;
+	if let Some(schemas) =
+		app_state
+			.schema_cache
+			.get(&app_state.engine, request.wasm.as_str(), module)?
+	{
+		schemas.validate_parameter(&Value::Object(request.parameter.clone()))?;
+	}
+	let module_hash = app_state
+		.module_cache
+		.get_module_hash(request.wasm.as_str())
+		.unwrap_or_default();
+	let mut program = Program::new(&app_state.engine, module)?;
+	let program_request = Request::Initialization {
+		parameter: parameter.clone(),
 	};
-	let process_id = Uuid::new_v4().to_string();
-	app_state.db_handler.insert(
+	let snapshot = program.execute_snapshot(&program_request)?;
+	let process_id = request
+		.process_id
+		.unwrap_or_else(|| Uuid::new_v4().to_string());
+	let metadata = app_state.db_handler.insert(
 		request.wasm.as_str(),
 		process_id.as_str(),
-		snapshot.state.as_str(),
+		parameter.as_str(),
+		&Transition {
+			state: snapshot.state.as_str(),
+			module_hash,
+			completion: snapshot.completion.as_ref(),
+			indexes: &snapshot.indexes,
+			operations: snapshot.operations.as_slice(),
+			event_status: snapshot.event_status,
+			idempotency: request.idempotency_key.as_deref().map(|key| Idempotency {
+				key,
+				fingerprint: fingerprint.as_str(),
+			}),
+		},
 	)?;
 	Ok(CreateResponse {
 		operations: snapshot.operations,
 		wasm: request.wasm,
 		process_id,
+		version: metadata.version,
+		metadata,
 		state: serde_json::from_str(snapshot.state.as_str())?,
+		completion: snapshot.completion,
+		replayed: false,
 	})
 }
+
+fn exists(app_state: &AppState, wasm: &str, process_id: &str) -> anyhow::Result<bool> {
+	match app_state.db_handler.get(wasm, process_id) {
+		Ok(_) => Ok(true),
+		Err(error)
+			if matches!(
+				error.downcast_ref::<HostError>(),
+				Some(HostError::NotFound("process_not_found"))
+			) =>
+		{
+			Ok(false)
+		}
+		Err(error) => Err(error),
+	}
+}
+
+/// Compares `parameter` with the one the existing process was initialized with. A process whose
+/// initialization was compacted away is assumed to match.
+fn same_parameter(
+	app_state: &AppState,
+	request: &CreateRequest,
+	parameter: &str,
+) -> anyhow::Result<bool> {
+	let process_id = request.process_id.as_deref().unwrap_or_default();
+	let history = app_state
+		.db_handler
+		.history(request.wasm.as_str(), process_id)?;
+	Ok(match history.first().map(|entry| &entry.input) {
+		Some(HistoryInput::Initialization { parameter: initial }) => initial == parameter,
+		_ => true,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeMap;
+
+	use common::{EventStatus, Response, Snapshot};
+	use serde_json::json;
+
+	use super::*;
+
+	fn request(process_id: &str) -> CreateRequest {
+		serde_json::from_value(json!({
+			"wasm": "a.wasm",
+			"parameter": { "count": 1 },
+			"idempotency_key": "k",
+			"process_id": process_id,
+		}))
+		.unwrap()
+	}
+
+	#[test]
+	fn test_idempotency() {
+		let app_state = AppState::temporary(&Response::Snapshot(Snapshot {
+			operations: Vec::new(),
+			state: r#"{"count":1}"#.to_string(),
+			completion: None,
+			indexes: BTreeMap::new(),
+			event_status: EventStatus::Consumed,
+			event_statuses: Vec::new(),
+		}));
+		let created = create(request("p"), &app_state).unwrap();
+		assert!(!created.replayed);
+		let replayed = create(request("p"), &app_state).unwrap();
+		assert!(replayed.replayed);
+		assert_eq!(replayed.process_id, "p");
+
+		let Err(error) = create(request("q"), &app_state) else {
+			panic!("expected the key to be rejected");
+		};
+		assert!(matches!(
+			error.downcast_ref::<HostError>(),
+			Some(HostError::Conflict("idempotency_key_reused"))
+		));
+		assert!(app_state.db_handler.get("a.wasm", "q").is_err());
+	}
+}
@@ -0,0 +1,39 @@
+use std::sync::Arc;
+
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{Path, Query, State};
+use serde::{Deserialize, Serialize};
+
+use crate::route::{parse_query, HandlerResponse};
+use crate::AppState;
+
+#[derive(Deserialize)]
+pub struct DeleteQuery {
+	expected_version: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct DeleteResponse {
+	wasm: String,
+	process_id: String,
+}
+
+pub async fn delete_handler(
+	State(state): State<Arc<AppState>>,
+	Path((wasm, process_id)): Path<(String, String)>,
+	query: Result<Query<DeleteQuery>, QueryRejection>,
+) -> HandlerResponse<DeleteResponse> {
+	let query = match parse_query(query) {
+		Ok(query) => query,
+		Err(error) => return HandlerResponse::Error(error),
+	};
+	let _guard = state
+		.process_locks
+		.lock(wasm.as_str(), process_id.as_str())
+		.await;
+	let result = state
+		.db_handler
+		.delete(wasm.as_str(), process_id.as_str(), query.expected_version)
+		.map(|_| DeleteResponse { wasm, process_id });
+	HandlerResponse::from_result(result)
+}
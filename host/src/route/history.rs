@@ -0,0 +1,14 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+
+use crate::db::HistoryEntry;
+use crate::route::HandlerResponse;
+use crate::AppState;
+
+pub async fn history_handler(
+	State(state): State<Arc<AppState>>,
+	Path((wasm, process_id)): Path<(String, String)>,
+) -> HandlerResponse<Vec<HistoryEntry>> {
+	HandlerResponse::from_result(state.db_handler.history(wasm.as_str(), process_id.as_str()))
+}
@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{Path, Query, State};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::db::ProcessMetadata;
+use crate::error::HostError;
+use crate::route::{parse_query, HandlerResponse};
+use crate::AppState;
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+	cursor: Option<String>,
+	limit: Option<usize>,
+	#[serde(default)]
+	include_state: bool,
+	index: Option<String>,
+	value: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ListResponse {
+	wasm: String,
+	processes: Vec<ProcessSummary>,
+	next_cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ProcessSummary {
+	process_id: String,
+	metadata: ProcessMetadata,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	state: Option<Map<String, Value>>,
+}
+
+pub async fn list_handler(
+	State(state): State<Arc<AppState>>,
+	Path(wasm): Path<String>,
+	query: Result<Query<ListQuery>, QueryRejection>,
+) -> HandlerResponse<ListResponse> {
+	match parse_query(query) {
+		Ok(query) => HandlerResponse::from_result(list(wasm, query, &state)),
+		Err(error) => HandlerResponse::Error(error),
+	}
+}
+
+fn list(wasm: String, query: ListQuery, app_state: &AppState) -> anyhow::Result<ListResponse> {
+	let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+	let cursor = query.cursor.as_deref();
+	let mut records = match (&query.index, &query.value) {
+		(Some(index), Some(value)) => app_state.db_handler.find(
+			wasm.as_str(),
+			index.as_str(),
+			value.as_str(),
+			cursor,
+			limit + 1,
+		)?,
+		(None, None) => app_state
+			.db_handler
+			.list(wasm.as_str(), cursor, limit + 1)?,
+		_ => {
+			return Err(HostError::bad_input(
+				"index_requires_value",
+				"index and value are given together",
+			)
+			.into())
+		}
+	};
+	let next_cursor = if records.len() > limit {
+		records.truncate(limit);
+		records.last().map(|(process_id, _)| process_id.clone())
+	} else {
+		None
+	};
+	let mut processes = Vec::new();
+	for (process_id, metadata) in records {
+		let state = match query.include_state {
+			true => {
+				let record = app_state
+					.db_handler
+					.get(wasm.as_str(), process_id.as_str())?;
+				Some(serde_json::from_str(record.state.as_str())?)
+			}
+			false => None,
+		};
+		processes.push(ProcessSummary {
+			process_id,
+			metadata,
+			state,
+		});
+	}
+	Ok(ListResponse {
+		wasm,
+		processes,
+		next_cursor,
+	})
+}
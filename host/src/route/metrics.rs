@@ -0,0 +1,11 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::Json;
+
+use crate::lock::LockMetrics;
+use crate::AppState;
+
+pub async fn lock_metrics_handler(State(state): State<Arc<AppState>>) -> Json<LockMetrics> {
+	state.process_locks.metrics().into()
+}
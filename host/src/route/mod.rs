@@ -1,29 +1,126 @@
-use std::error::Error;
-use std::fmt::Display;
-
-use axum::http::StatusCode;
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::Query;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
 use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::db::IdempotencyRecord;
+use crate::error::HostError;
 
+pub use archive::{archive_handler, archived_handler};
+pub use batch::batch_handler;
+pub use completion::completion_handler;
 pub use create::create_handler;
-pub use update::update_handler;
+pub use delete::delete_handler;
+pub use history::history_handler;
+pub use list::list_handler;
+pub use metrics::lock_metrics_handler;
+pub use outbox::{acknowledge_handler, outbox_handler};
+pub use process::process_handler;
+pub use query::query_handler;
+pub use replay::replay_handler;
+pub use schema::schema_handler;
+pub use update::{simulate_handler, update_handler};
+mod archive;
+mod batch;
+mod completion;
 mod create;
+mod delete;
+mod history;
+mod list;
+mod metrics;
+mod outbox;
+mod process;
+mod query;
+mod replay;
+mod schema;
 mod update;
 
-#[derive(Serialize)]
-#[serde(untagged)]
 pub enum HandlerResponse<T> {
 	Value(T),
-	Error { status_code: u16, error: String },
+	Error(HostError),
+}
+
+/// Body of every failed request. `error` is the machine-readable code and `kind` tells guest
+/// failures (`guest`, `trap`) apart from host ones. Guest errors and schema violations add
+/// `details`, and guest errors may be `retryable`.
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+	status_code: u16,
+	error: &'a str,
+	kind: &'static str,
+	message: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	details: Option<Value>,
+	#[serde(skip_serializing_if = "std::ops::Not::not")]
+	retryable: bool,
 }
 
 impl<T: Serialize> HandlerResponse<T> {
-	pub fn from_result<E: Display>(result: Result<T, E>) -> HandlerResponse<T> {
+	pub fn from_result(result: anyhow::Result<T>) -> HandlerResponse<T> {
 		match result {
 			Ok(t) => HandlerResponse::Value(t),
-			Err(e) => HandlerResponse::Error {
-				status_code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
-				error: e.to_string(),
-			},
+			Err(e) => HandlerResponse::Error(HostError::from_anyhow(e)),
+		}
+	}
+}
+
+impl<T: Serialize> IntoResponse for HandlerResponse<T> {
+	fn into_response(self) -> Response {
+		match self {
+			HandlerResponse::Value(value) => Json(value).into_response(),
+			HandlerResponse::Error(error) => {
+				let status_code = error.status_code();
+				let body = ErrorBody {
+					status_code: status_code.as_u16(),
+					error: error.code(),
+					kind: error.kind(),
+					message: match &error {
+						HostError::Guest(guest_error) => guest_error.message.clone(),
+						_ => error.to_string(),
+					},
+					details: error.details(),
+					retryable: error.retryable(),
+				};
+				(status_code, Json(body)).into_response()
+			}
+		}
+	}
+}
+
+/// Turns a body that failed to parse into a `bad_input` error instead of axum's plain-text
+/// rejection.
+pub fn parse_body<T>(body: Result<Json<T>, JsonRejection>) -> Result<T, HostError> {
+	body.map(|Json(body)| body)
+		.map_err(|rejection| HostError::bad_input("invalid_body", rejection.body_text()))
+}
+
+pub fn parse_query<T>(query: Result<Query<T>, QueryRejection>) -> Result<T, HostError> {
+	query
+		.map(|Query(query)| query)
+		.map_err(|rejection| HostError::bad_input("invalid_query", rejection.body_text()))
+}
+
+/// Hex SHA-256 of the parts of a request that have to match when its idempotency key is reused.
+pub fn fingerprint<T: Serialize>(request: &T) -> anyhow::Result<String> {
+	Ok(format!(
+		"{:x}",
+		Sha256::digest(serde_json::to_vec(request)?.as_slice())
+	))
+}
+
+/// Returns the stored result of a retried request, failing if the key was used for a
+/// different request.
+pub fn check_idempotency(
+	record: Option<IdempotencyRecord>,
+	fingerprint: &str,
+) -> anyhow::Result<Option<IdempotencyRecord>> {
+	match record {
+		Some(record) if record.fingerprint != fingerprint => {
+			Err(HostError::Conflict("idempotency_key_reused").into())
 		}
+		record => Ok(record),
 	}
 }
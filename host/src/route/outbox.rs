@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{Query, State};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+use crate::db::OutboxEntry;
+use crate::route::{parse_body, parse_query, HandlerResponse};
+use crate::AppState;
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct OutboxQuery {
+	cursor: Option<String>,
+	limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct OutboxResponse {
+	entries: Vec<OutboxEntry>,
+	next_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AcknowledgeRequest {
+	ids: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct AcknowledgeResponse {
+	acknowledged: usize,
+}
+
+/// Returns pending events. Entries stay in the outbox until they are acknowledged, so a
+/// dispatcher that fails before acknowledging delivers them again.
+pub async fn outbox_handler(
+	State(state): State<Arc<AppState>>,
+	query: Result<Query<OutboxQuery>, QueryRejection>,
+) -> HandlerResponse<OutboxResponse> {
+	match parse_query(query) {
+		Ok(query) => HandlerResponse::from_result(outbox(query, &state)),
+		Err(error) => HandlerResponse::Error(error),
+	}
+}
+
+pub async fn acknowledge_handler(
+	State(state): State<Arc<AppState>>,
+	request: Result<Json<AcknowledgeRequest>, JsonRejection>,
+) -> HandlerResponse<AcknowledgeResponse> {
+	let request = match parse_body(request) {
+		Ok(request) => request,
+		Err(error) => return HandlerResponse::Error(error),
+	};
+	HandlerResponse::from_result(
+		state
+			.db_handler
+			.acknowledge(request.ids.as_slice())
+			.map(|acknowledged| AcknowledgeResponse { acknowledged }),
+	)
+}
+
+fn outbox(query: OutboxQuery, app_state: &AppState) -> anyhow::Result<OutboxResponse> {
+	let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+	let mut entries = app_state
+		.db_handler
+		.pending_operations(query.cursor.as_deref(), limit + 1)?;
+	let next_cursor = if entries.len() > limit {
+		entries.truncate(limit);
+		entries.last().map(|entry| entry.id.clone())
+	} else {
+		None
+	};
+	Ok(OutboxResponse {
+		entries,
+		next_cursor,
+	})
+}
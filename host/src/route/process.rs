@@ -0,0 +1,126 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::header::{ETAG, IF_NONE_MATCH};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::db::ProcessMetadata;
+use crate::route::HandlerResponse;
+use crate::AppState;
+
+#[derive(Serialize)]
+pub struct ProcessResponse {
+	wasm: String,
+	process_id: String,
+	version: u64,
+	metadata: ProcessMetadata,
+	state: Map<String, Value>,
+}
+
+/// Returns the current state of a process. The ETag names the version, so a client polling
+/// with `If-None-Match` gets `304 Not Modified` until the process is written again.
+pub async fn process_handler(
+	State(state): State<Arc<AppState>>,
+	Path((wasm, process_id)): Path<(String, String)>,
+	headers: HeaderMap,
+) -> Response {
+	let metadata = match state
+		.db_handler
+		.get_metadata(wasm.as_str(), process_id.as_str())
+	{
+		Ok(metadata) => metadata,
+		Err(error) => return error_response(error),
+	};
+	let current = etag(&metadata);
+	if matches(&headers, current.as_str()) {
+		return with_etag(StatusCode::NOT_MODIFIED.into_response(), current.as_str());
+	}
+	let (record, metadata) = match state
+		.db_handler
+		.get_with_metadata(wasm.as_str(), process_id.as_str())
+	{
+		Ok(read) => read,
+		Err(error) => return error_response(error),
+	};
+	let state = match serde_json::from_str(record.state.as_str()) {
+		Ok(state) => state,
+		Err(error) => return error_response(error.into()),
+	};
+	let etag = etag(&metadata);
+	let response = ProcessResponse {
+		wasm,
+		process_id,
+		version: record.version,
+		metadata,
+		state,
+	};
+	with_etag(
+		HandlerResponse::Value(response).into_response(),
+		etag.as_str(),
+	)
+}
+
+/// A `304 Not Modified` carries the same tag as the `200` it stands for.
+fn with_etag(mut response: Response, etag: &str) -> Response {
+	if let Ok(etag) = HeaderValue::from_str(etag) {
+		response.headers_mut().insert(ETAG, etag);
+	}
+	response
+}
+
+/// Includes the creation time so a process deleted and created again under the same id does
+/// not match a tag of its predecessor.
+fn etag(metadata: &ProcessMetadata) -> String {
+	format!("\"{}-{}\"", metadata.created_at, metadata.version)
+}
+
+fn matches(headers: &HeaderMap, etag: &str) -> bool {
+	headers
+		.get_all(IF_NONE_MATCH)
+		.iter()
+		.filter_map(|value| value.to_str().ok())
+		.flat_map(|value| value.split(','))
+		.map(|tag| tag.trim().trim_start_matches("W/"))
+		.any(|tag| tag == etag || tag == "*")
+}
+
+fn error_response(error: anyhow::Error) -> Response {
+	HandlerResponse::<ProcessResponse>::from_result(Err(error)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+	use common::Response as GuestResponse;
+
+	use super::*;
+	use crate::db::transition;
+
+	#[tokio::test]
+	async fn test_not_modified() {
+		let app_state = AppState::temporary(&GuestResponse::QueryResult("null".to_string()));
+		app_state
+			.db_handler
+			.insert("a.wasm", "p", "{}", &transition("{}", "hash"))
+			.unwrap();
+		let app_state = Arc::new(app_state);
+		let get = |headers: HeaderMap| {
+			process_handler(
+				State(app_state.clone()),
+				Path(("a.wasm".to_string(), "p".to_string())),
+				headers,
+			)
+		};
+
+		let response = get(HeaderMap::new()).await;
+		assert_eq!(response.status(), StatusCode::OK);
+		let etag = response.headers()[ETAG].clone();
+		let mut headers = HeaderMap::new();
+		headers.insert(IF_NONE_MATCH, etag.clone());
+		let response = get(headers).await;
+		assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+		assert_eq!(response.headers()[ETAG], etag);
+	}
+}
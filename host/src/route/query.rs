@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{Path, State};
+use axum::Json;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::error::HostError;
+use crate::route::{parse_body, HandlerResponse};
+use crate::wasm::Program;
+use crate::AppState;
+
+#[derive(Serialize)]
+pub struct QueryResponse {
+	wasm: String,
+	process_id: String,
+	version: u64,
+	result: Value,
+}
+
+pub async fn query_handler(
+	State(state): State<Arc<AppState>>,
+	Path((wasm, process_id)): Path<(String, String)>,
+	query: Result<Json<Value>, JsonRejection>,
+) -> HandlerResponse<QueryResponse> {
+	match parse_body(query) {
+		Ok(query) => HandlerResponse::from_result(execute_query(wasm, process_id, query, &state)),
+		Err(error) => HandlerResponse::Error(error),
+	}
+}
+
+/// Answers `query` from the current state of a process. Queries only read, so they take no
+/// lock and nothing about them is stored, not even a failure.
+fn execute_query(
+	wasm: String,
+	process_id: String,
+	query: Value,
+	app_state: &AppState,
+) -> anyhow::Result<QueryResponse> {
+	let module = app_state
+		.module_cache
+		.get_module(wasm.as_str())
+		.ok_or(HostError::NotFound("module_not_found"))?;
+	let record = app_state
+		.db_handler
+		.get(wasm.as_str(), process_id.as_str())?;
+	let mut program = Program::new(&app_state.engine, module)?;
+	let result = program.query(record.state, serde_json::to_string(&query)?)?;
+	Ok(QueryResponse {
+		result: serde_json::from_str(result.as_str())
+			.map_err(|error| HostError::trap(error.into()))?,
+		wasm,
+		process_id,
+		version: record.version,
+	})
+}
@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use common::Request;
+
+use crate::db::HistoryInput;
+use crate::error::HostError;
+use crate::route::batch::execute_events;
+use crate::route::update::execute_event;
+use crate::route::HandlerResponse;
+use crate::wasm::Program;
+use crate::AppState;
+
+#[derive(Serialize)]
+pub struct ReplayResponse {
+	wasm: String,
+	process_id: String,
+	from_snapshot: Option<u64>,
+	version: u64,
+	state: Map<String, Value>,
+	matches_stored: bool,
+}
+
+pub async fn replay_handler(
+	State(state): State<Arc<AppState>>,
+	Path((wasm, process_id)): Path<(String, String)>,
+) -> HandlerResponse<ReplayResponse> {
+	HandlerResponse::from_result(replay(wasm, process_id, &state))
+}
+
+/// Rebuilds the state of a process from its latest snapshot and the history written after it.
+fn replay(
+	wasm: String,
+	process_id: String,
+	app_state: &AppState,
+) -> anyhow::Result<ReplayResponse> {
+	let module = app_state
+		.module_cache
+		.get_module(wasm.as_str())
+		.ok_or(HostError::NotFound("module_not_found"))?;
+	let record = app_state
+		.db_handler
+		.get(wasm.as_str(), process_id.as_str())?;
+	let (snapshot, history) = app_state
+		.db_handler
+		.replay_start(wasm.as_str(), process_id.as_str())?;
+	let from_snapshot = snapshot.as_ref().map(|snapshot| snapshot.version);
+	let (mut version, mut state) = match snapshot {
+		Some(snapshot) => (snapshot.version, Some(snapshot.state)),
+		None => (0, None),
+	};
+	for entry in history {
+		let snapshot = match (entry.input, state) {
+			(HistoryInput::Initialization { parameter }, None) => {
+				let mut program = Program::new(&app_state.engine, module)?;
+				program.execute_snapshot(&Request::Initialization { parameter })?
+			}
+			(HistoryInput::Event { event, .. }, Some(state)) => {
+				execute_event(app_state, module, state, event, false)?
+			}
+			(HistoryInput::Events { events, .. }, Some(state)) => {
+				execute_events(app_state, module, state, events)?
+			}
+			_ => return Err(HostError::NotFound("history_incomplete").into()),
+		};
+		version = entry.version;
+		state = Some(snapshot.state);
+	}
+	let state = state.ok_or(HostError::NotFound("history_incomplete"))?;
+	Ok(ReplayResponse {
+		matches_stored: version == record.version && state == record.state,
+		state: serde_json::from_str(state.as_str())?,
+		wasm,
+		process_id,
+		from_snapshot,
+		version,
+	})
+}
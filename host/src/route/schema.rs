@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::error::HostError;
+use crate::route::HandlerResponse;
+use crate::AppState;
+
+#[derive(Serialize)]
+pub struct SchemaResponse {
+	wasm: String,
+	parameter: Value,
+	event: Value,
+	state: Value,
+	query: Value,
+}
+
+pub async fn schema_handler(
+	State(state): State<Arc<AppState>>,
+	Path(wasm): Path<String>,
+) -> HandlerResponse<SchemaResponse> {
+	HandlerResponse::from_result(schema(wasm, &state))
+}
+
+/// Returns the JSON Schemas a module describes its parameter, events, state and queries with,
+/// from the schema cache. Modules without usable schemas have none to return.
+fn schema(wasm: String, app_state: &AppState) -> anyhow::Result<SchemaResponse> {
+	let module = app_state
+		.module_cache
+		.get_module(wasm.as_str())
+		.ok_or(HostError::NotFound("module_not_found"))?;
+	let schemas = app_state
+		.schema_cache
+		.get(&app_state.engine, wasm.as_str(), module)?
+		.ok_or(HostError::NotFound("schema_not_found"))?;
+	let schemas = schemas.described();
+	let parse = |schema: &String| {
+		serde_json::from_str(schema.as_str()).map_err(|error| HostError::trap(error.into()))
+	};
+	Ok(SchemaResponse {
+		wasm,
+		parameter: parse(&schemas.parameter)?,
+		event: parse(&schemas.event)?,
+		state: parse(&schemas.state)?,
+		query: parse(&schemas.query)?,
+	})
+}
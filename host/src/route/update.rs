@@ -1,13 +1,18 @@
 use std::sync::Arc;
 
+use axum::extract::rejection::JsonRejection;
 use axum::extract::State;
 use axum::Json;
+use json_patch::Patch;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
+use wasmtime::Module;
 
-use common::{Operation, Request, Response};
+use common::{Completion, EventStatus, Operation, Request, Snapshot};
 
-use crate::route::HandlerResponse;
+use crate::db::{Idempotency, IdempotencyRecord, ProcessMetadata, Transition};
+use crate::error::HostError;
+use crate::route::{check_idempotency, fingerprint, parse_body, HandlerResponse};
 use crate::wasm::Program;
 use crate::AppState;
 
@@ -16,49 +21,436 @@ pub struct UpdateRequest {
 	wasm: String,
 	process_id: String,
 	event: Map<String, Value>,
+	expected_version: Option<u64>,
+	idempotency_key: Option<String>,
+	#[serde(default)]
+	on_dropped: OnDropped,
+	/// Fails a dropped event whatever `on_dropped` says, with details naming every state the
+	/// event was offered to and why it did not parse.
+	#[serde(default)]
+	diagnose: bool,
+}
+
+/// How an update reports an event that no state accepted.
+#[derive(Deserialize, Default, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnDropped {
+	#[default]
+	Success,
+	/// Succeeds with an `event_dropped` warning.
+	Warning,
+	/// Fails with an `event_dropped` error and stores nothing.
+	Error,
 }
 
 #[derive(Serialize)]
 pub struct UpdateResponse {
 	wasm: String,
 	process_id: String,
+	version: u64,
+	metadata: ProcessMetadata,
+	state: Map<String, Value>,
+	operations: Vec<Operation>,
+	completion: Option<Completion>,
+	event_status: EventStatus,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	warnings: Vec<&'static str>,
+	#[serde(skip_serializing_if = "std::ops::Not::not")]
+	replayed: bool,
+}
+
+/// What an update would do. Nothing is stored, so `version` is the version the event was
+/// run against and `diff` is a JSON Patch from its state to the would-be `state`.
+#[derive(Serialize)]
+pub struct SimulateResponse {
+	wasm: String,
+	process_id: String,
+	version: u64,
 	state: Map<String, Value>,
 	operations: Vec<Operation>,
+	completion: Option<Completion>,
+	event_status: EventStatus,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	warnings: Vec<&'static str>,
+	diff: Patch,
+}
+
+impl UpdateResponse {
+	fn replayed(
+		wasm: String,
+		record: IdempotencyRecord,
+		on_dropped: OnDropped,
+	) -> anyhow::Result<UpdateResponse> {
+		Ok(UpdateResponse {
+			warnings: warnings(&[record.event_status], on_dropped),
+			event_status: record.event_status,
+			wasm,
+			process_id: record.process_id,
+			version: record.metadata.version,
+			metadata: record.metadata,
+			state: serde_json::from_str(record.state.as_str())?,
+			operations: record.operations,
+			completion: record.completion,
+			replayed: true,
+		})
+	}
 }
 
 pub async fn update_handler(
 	State(state): State<Arc<AppState>>,
-	request: Json<UpdateRequest>,
-) -> Json<HandlerResponse<UpdateResponse>> {
-	HandlerResponse::from_result(update(request.0, &state)).into()
+	request: Result<Json<UpdateRequest>, JsonRejection>,
+) -> HandlerResponse<UpdateResponse> {
+	let request = match parse_body(request) {
+		Ok(request) => request,
+		Err(error) => return HandlerResponse::Error(error),
+	};
+	let _guard = state
+		.process_locks
+		.lock(request.wasm.as_str(), request.process_id.as_str())
+		.await;
+	HandlerResponse::from_result(update(request, &state))
+}
+
+/// Previews an update: takes the same request but stores nothing and dispatches no event.
+pub async fn simulate_handler(
+	State(state): State<Arc<AppState>>,
+	request: Result<Json<UpdateRequest>, JsonRejection>,
+) -> HandlerResponse<SimulateResponse> {
+	match parse_body(request) {
+		Ok(request) => HandlerResponse::from_result(simulate(request, &state)),
+		Err(error) => HandlerResponse::Error(error),
+	}
 }
 
 fn update(request: UpdateRequest, app_state: &AppState) -> anyhow::Result<UpdateResponse> {
-	let module = app_state
+	let fingerprint = fingerprint(&(&request.event, request.expected_version))?;
+	if let Some(key) = request.idempotency_key.as_deref() {
+		let record = app_state.db_handler.get_idempotency(
+			request.wasm.as_str(),
+			Some(request.process_id.as_str()),
+			key,
+		)?;
+		if let Some(record) = check_idempotency(record, fingerprint.as_str())? {
+			return UpdateResponse::replayed(request.wasm, record, request.on_dropped);
+		}
+	}
+	let execution = execute_update(&request, app_state, false)?;
+	let snapshot = execution.snapshot;
+	let module_hash = app_state
 		.module_cache
-		.get_module(request.wasm.as_str())
-		.ok_or(anyhow::Error::msg("module_not_found"))?;
-	let state = app_state
-		.db_handler
-		.get(request.wasm.as_str(), request.process_id.as_str())?;
-
-	let mut program = Program::new(&app_state.engine, &module)?;
-	let event = serde_json::to_string(&request.event)?;
-	let program_request = Request::Event { state, event };
-	let response = program.execute_request(&program_request)?;
-	let snapshot = match response {
-		Response::Error(e) => return Err(anyhow::Error::msg(e)),
-		Response::Snapshot(s) => s,
-	};
-	app_state.db_handler.insert(
+		.get_module_hash(request.wasm.as_str())
+		.unwrap_or_default();
+	let metadata = app_state.db_handler.compare_and_swap(
 		request.wasm.as_str(),
 		request.process_id.as_str(),
-		snapshot.state.as_str(),
+		execution.version,
+		execution.event.as_str(),
+		&Transition {
+			state: snapshot.state.as_str(),
+			module_hash,
+			completion: snapshot.completion.as_ref(),
+			indexes: &snapshot.indexes,
+			operations: snapshot.operations.as_slice(),
+			event_status: snapshot.event_status,
+			idempotency: request.idempotency_key.as_deref().map(|key| Idempotency {
+				key,
+				fingerprint: fingerprint.as_str(),
+			}),
+		},
 	)?;
 	Ok(UpdateResponse {
 		operations: snapshot.operations,
 		wasm: request.wasm,
 		process_id: request.process_id,
+		version: metadata.version,
+		metadata,
 		state: serde_json::from_str(snapshot.state.as_str())?,
+		completion: snapshot.completion,
+		event_status: snapshot.event_status,
+		warnings: warnings(&[snapshot.event_status], request.on_dropped),
+		replayed: false,
+	})
+}
+
+fn simulate(request: UpdateRequest, app_state: &AppState) -> anyhow::Result<SimulateResponse> {
+	let execution = execute_update(&request, app_state, true)?;
+	let snapshot = execution.snapshot;
+	let current: Value = serde_json::from_str(execution.state.as_str())?;
+	let state: Map<String, Value> = serde_json::from_str(snapshot.state.as_str())?;
+	Ok(SimulateResponse {
+		diff: json_patch::diff(&current, &Value::Object(state.clone())),
+		wasm: request.wasm,
+		process_id: request.process_id,
+		version: execution.version,
+		state,
+		operations: snapshot.operations,
+		completion: snapshot.completion,
+		event_status: snapshot.event_status,
+		warnings: warnings(&[snapshot.event_status], request.on_dropped),
 	})
 }
+
+/// Result of running an update's event against the current state of the process.
+struct Execution {
+	/// Version and state the event was run against.
+	version: u64,
+	state: String,
+	event: String,
+	snapshot: Snapshot,
+}
+
+/// Runs every step of an update up to storing its result. With `dry_run` a guest failure is
+/// not recorded either, so nothing is written at all.
+fn execute_update(
+	request: &UpdateRequest,
+	app_state: &AppState,
+	dry_run: bool,
+) -> anyhow::Result<Execution> {
+	let module = app_state
+		.module_cache
+		.get_module(request.wasm.as_str())
+		.ok_or(HostError::NotFound("module_not_found"))?;
+	if let Some(schemas) =
+		app_state
+			.schema_cache
+			.get(&app_state.engine, request.wasm.as_str(), module)?
+	{
+		schemas.validate_event(&Value::Object(request.event.clone()))?;
+	}
+	let (version, state) = load_state(
+		app_state,
+		request.wasm.as_str(),
+		request.process_id.as_str(),
+		request.expected_version,
+	)?;
+	let event = serde_json::to_string(&request.event)?;
+	let result = execute_event(
+		app_state,
+		module,
+		state.clone(),
+		event.clone(),
+		request.diagnose,
+	);
+	let snapshot = match result {
+		Ok(snapshot) => snapshot,
+		Err(error) => {
+			// A simulation never writes.
+			if !dry_run {
+				record_failure(
+					app_state,
+					request.wasm.as_str(),
+					request.process_id.as_str(),
+					version,
+					&error,
+				);
+			}
+			return Err(error);
+		}
+	};
+	if snapshot.event_status == EventStatus::Dropped && request.on_dropped == OnDropped::Error {
+		return Err(HostError::EventDropped("no state accepted the event".to_string()).into());
+	}
+	Ok(Execution {
+		version,
+		state,
+		event,
+		snapshot,
+	})
+}
+
+/// Loads the version and state an update runs against, checking that the process is still at
+/// `expected_version` and accepts events.
+pub fn load_state(
+	app_state: &AppState,
+	wasm: &str,
+	process_id: &str,
+	expected_version: Option<u64>,
+) -> anyhow::Result<(u64, String)> {
+	let record = app_state.db_handler.get(wasm, process_id)?;
+	if let Some(expected_version) = expected_version {
+		if expected_version != record.version {
+			return Err(HostError::Conflict("version_conflict").into());
+		}
+	}
+	let metadata = app_state.db_handler.get_metadata(wasm, process_id)?;
+	if metadata.status.is_final() {
+		return Err(HostError::Conflict("process_completed").into());
+	}
+	Ok((record.version, record.state))
+}
+
+/// Marks the process as errored after a failed execution. A dropped event is not a failure of
+/// the process, even when it is diagnosed, and neither is a batch rejected for going on past
+/// the outcome of the process. Only the executor reports these, so a state failing with the
+/// same code is still recorded.
+pub fn record_failure(
+	app_state: &AppState,
+	wasm: &str,
+	process_id: &str,
+	version: u64,
+	error: &anyhow::Error,
+) {
+	let rejected = matches!(
+		error.downcast_ref::<HostError>(),
+		Some(HostError::Guest(guest_error)) if guest_error.is_executor("event_dropped")
+			|| guest_error.is_executor("process_completed")
+	);
+	if rejected {
+		return;
+	}
+	let result =
+		app_state
+			.db_handler
+			.record_error(wasm, process_id, version, error.to_string().as_str());
+	if let Err(record_error) = result {
+		println!("record_error_failed: {}", record_error);
+	}
+}
+
+/// Warnings for the statuses of the events of an update or a batch.
+pub fn warnings(event_statuses: &[EventStatus], on_dropped: OnDropped) -> Vec<&'static str> {
+	match on_dropped {
+		OnDropped::Warning if event_statuses.contains(&EventStatus::Dropped) => {
+			vec!["event_dropped"]
+		}
+		_ => Vec::new(),
+	}
+}
+
+pub fn execute_event(
+	app_state: &AppState,
+	module: &Module,
+	state: String,
+	event: String,
+	diagnose: bool,
+) -> anyhow::Result<Snapshot> {
+	let mut program = Program::new(&app_state.engine, module)?;
+	let program_request = Request::Event {
+		state,
+		event,
+		diagnose,
+	};
+	program.execute_snapshot(&program_request)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeMap;
+
+	use common::{GuestError, Response};
+	use serde_json::json;
+
+	use super::*;
+	use crate::db::transition;
+
+	fn app_state(response: Response) -> AppState {
+		let app_state = AppState::temporary(&response);
+		app_state
+			.db_handler
+			.insert(
+				"a.wasm",
+				"p",
+				"{}",
+				&transition(r#"{"count":1,"items":["a"]}"#, "hash"),
+			)
+			.unwrap();
+		app_state
+	}
+
+	fn request() -> UpdateRequest {
+		serde_json::from_value(json!({
+			"wasm": "a.wasm",
+			"process_id": "p",
+			"event": { "add": "b" },
+		}))
+		.unwrap()
+	}
+
+	/// Everything an update could write for process `p`.
+	fn written(app_state: &AppState) -> (String, ProcessMetadata, usize, usize) {
+		let db_handler = &app_state.db_handler;
+		(
+			db_handler.get("a.wasm", "p").unwrap().state,
+			db_handler.get_metadata("a.wasm", "p").unwrap(),
+			db_handler.history("a.wasm", "p").unwrap().len(),
+			db_handler.pending_operations(None, 10).unwrap().len(),
+		)
+	}
+
+	#[test]
+	fn test_simulate() {
+		let app_state = app_state(Response::Snapshot(Snapshot {
+			operations: vec![Operation::Event("added".to_string())],
+			state: r#"{"count":2,"items":["a","b"],"last":"b"}"#.to_string(),
+			completion: None,
+			indexes: BTreeMap::new(),
+			event_status: EventStatus::Consumed,
+			event_statuses: Vec::new(),
+		}));
+		let before = written(&app_state);
+
+		let response = simulate(request(), &app_state).unwrap();
+		assert_eq!(written(&app_state), before);
+		assert_eq!(response.version, before.1.version);
+		assert_eq!(response.operations.len(), 1);
+		assert_eq!(
+			serde_json::to_value(&response.diff).unwrap(),
+			json!([
+				{ "op": "replace", "path": "/count", "value": 2 },
+				{ "op": "add", "path": "/items/1", "value": "b" },
+				{ "op": "add", "path": "/last", "value": "b" },
+			])
+		);
+		let mut state: Value = serde_json::from_str(before.0.as_str()).unwrap();
+		json_patch::patch(&mut state, &response.diff).unwrap();
+		assert_eq!(state, Value::Object(response.state));
+
+		update(request(), &app_state).unwrap();
+		assert_ne!(written(&app_state), before);
+	}
+
+	#[test]
+	fn test_simulate_failure() {
+		let app_state = app_state(Response::Error(GuestError::new(
+			"negative_count",
+			"count cannot go below zero",
+		)));
+		let before = written(&app_state);
+
+		let Err(error) = simulate(request(), &app_state) else {
+			panic!("expected the guest error");
+		};
+		assert!(matches!(
+			error.downcast_ref::<HostError>(),
+			Some(HostError::Guest(guest_error)) if guest_error.code == "negative_count"
+		));
+		assert_eq!(written(&app_state), before);
+
+		assert!(update(request(), &app_state).is_err());
+		assert_ne!(written(&app_state).1, before.1);
+	}
+
+	#[test]
+	fn test_dropped_event_error() {
+		let app_state = app_state(Response::Snapshot(Snapshot {
+			operations: Vec::new(),
+			state: r#"{"count":1,"items":["a"]}"#.to_string(),
+			completion: None,
+			indexes: BTreeMap::new(),
+			event_status: EventStatus::Dropped,
+			event_statuses: Vec::new(),
+		}));
+		let before = written(&app_state);
+		let mut request = request();
+		request.on_dropped = OnDropped::Error;
+
+		let Err(error) = update(request, &app_state) else {
+			panic!("expected the drop to fail the update");
+		};
+		let error = HostError::from_anyhow(error);
+		assert_eq!(error.code(), "event_dropped");
+		assert_eq!(error.kind(), "bad_input");
+		assert_eq!(written(&app_state), before);
+		assert!(written(&app_state).1.last_error.is_none());
+	}
+}
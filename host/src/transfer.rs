@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+use crate::db::{form_key, ConflictPolicy, DbHandler, ExportedProcess, ImportResult};
+use crate::error::HostError;
+
+const PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct ImportReport {
+	pub imported: usize,
+	pub overwritten: usize,
+	pub skipped: usize,
+}
+
+/// Handles `export <file>` and `import <file> [--dry-run] [--on-conflict skip|overwrite|fail]`.
+pub fn run(db_handler: &DbHandler, args: &[String]) -> anyhow::Result<()> {
+	match args {
+		[command, path] if command == "export" => {
+			let count = export(db_handler, File::create(path)?)?;
+			println!("exported {} processes", count);
+		}
+		[command, path, options @ ..] if command == "import" => {
+			let mut policy = ConflictPolicy::Fail;
+			let mut dry_run = false;
+			let mut options = options.iter();
+			while let Some(option) = options.next() {
+				match option.as_str() {
+					"--dry-run" => dry_run = true,
+					"--on-conflict" => {
+						let value = options
+							.next()
+							.ok_or(anyhow::Error::msg("missing_conflict_policy"))?;
+						policy = serde_json::from_value(serde_json::Value::String(value.clone()))?;
+					}
+					option => return Err(anyhow::Error::msg(format!("unknown_option: {}", option))),
+				}
+			}
+			let report = import(db_handler, path, policy, dry_run)?;
+			println!(
+				"{}imported {}, overwritten {}, skipped {}",
+				if dry_run { "dry run: " } else { "" },
+				report.imported,
+				report.overwritten,
+				report.skipped
+			);
+		}
+		_ => return Err(anyhow::Error::msg("usage: host [export <file> | import <file> [--dry-run] [--on-conflict skip|overwrite|fail]]")),
+	}
+	Ok(())
+}
+
+/// Writes every live process as one JSON line and returns how many were written.
+pub fn export<W: Write>(db_handler: &DbHandler, writer: W) -> anyhow::Result<usize> {
+	let mut writer = BufWriter::new(writer);
+	let mut cursor: Option<String> = None;
+	let mut count = 0;
+	loop {
+		let page = db_handler.export(cursor.as_deref(), PAGE_SIZE)?;
+		for process in page.iter() {
+			serde_json::to_writer(&mut writer, process)?;
+			writer.write_all(b"\n")?;
+		}
+		count += page.len();
+		cursor = page
+			.last()
+			.map(|process| form_key(process.wasm.as_str(), process.process_id.as_str()));
+		if page.len() < PAGE_SIZE {
+			writer.flush()?;
+			return Ok(count);
+		}
+	}
+}
+
+/// Checks the whole file before writing anything, so an invalid line, a process listed twice or a
+/// conflict under [`ConflictPolicy::Fail`] leaves the store untouched.
+pub fn import(
+	db_handler: &DbHandler,
+	path: &str,
+	policy: ConflictPolicy,
+	dry_run: bool,
+) -> anyhow::Result<ImportReport> {
+	let report = import_lines(db_handler, BufReader::new(File::open(path)?), policy, true)?;
+	if dry_run {
+		return Ok(report);
+	}
+	import_lines(db_handler, BufReader::new(File::open(path)?), policy, false)
+}
+
+fn import_lines<R: BufRead>(
+	db_handler: &DbHandler,
+	reader: R,
+	policy: ConflictPolicy,
+	dry_run: bool,
+) -> anyhow::Result<ImportReport> {
+	let mut report = ImportReport::default();
+	let mut seen = HashMap::new();
+	for (index, line) in reader.lines().enumerate() {
+		let line = line?;
+		if line.trim().is_empty() {
+			continue;
+		}
+		let result = serde_json::from_str::<ExportedProcess>(line.as_str())
+			.map_err(anyhow::Error::from)
+			.and_then(|process| {
+				let key = form_key(process.wasm.as_str(), process.process_id.as_str());
+				if let Some(first) = seen.insert(key.clone(), index + 1) {
+					return Err(HostError::bad_input(
+						"duplicate_process",
+						format!("{} is already on line {}", key, first),
+					)
+					.into());
+				}
+				db_handler.import(&process, policy, dry_run)
+			})
+			.map_err(|error| anyhow::Error::msg(format!("line {}: {}", index + 1, error)))?;
+		match result {
+			ImportResult::Imported => report.imported += 1,
+			ImportResult::Overwritten => report.overwritten += 1,
+			ImportResult::Skipped => report.skipped += 1,
+		}
+	}
+	Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::db::{transition, DbHandler};
+
+	fn handler_with(process_ids: &[&str]) -> DbHandler {
+		let db_handler = DbHandler::temporary();
+		for process_id in process_ids {
+			db_handler
+				.insert("a.wasm", process_id, "{}", &transition("{}", "hash"))
+				.unwrap();
+		}
+		db_handler
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let source = handler_with(&["p", "q"]);
+		let mut exported = Vec::new();
+		assert_eq!(export(&source, &mut exported).unwrap(), 2);
+
+		let target = handler_with(&["p"]);
+		let error =
+			import_lines(&target, exported.as_slice(), ConflictPolicy::Fail, true).unwrap_err();
+		assert_eq!(error.to_string(), "line 1: process_already_exists");
+		let report =
+			import_lines(&target, exported.as_slice(), ConflictPolicy::Skip, false).unwrap();
+		assert_eq!(
+			report,
+			ImportReport {
+				imported: 1,
+				overwritten: 0,
+				skipped: 1,
+			}
+		);
+		assert!(target.get("a.wasm", "q").is_ok());
+
+		let error = import_lines(&target, &b"{}\n"[..], ConflictPolicy::Skip, true).unwrap_err();
+		assert!(error.to_string().starts_with("line 1: "));
+
+		let mut duplicated = exported.clone();
+		duplicated.extend_from_slice(exported.as_slice());
+		let error = import_lines(
+			&handler_with(&[]),
+			duplicated.as_slice(),
+			ConflictPolicy::Overwrite,
+			true,
+		)
+		.unwrap_err();
+		assert_eq!(
+			error.to_string(),
+			"line 3: duplicate_process: a.wasm::p is already on line 1"
+		);
+	}
+}
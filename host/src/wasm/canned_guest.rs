@@ -0,0 +1,28 @@
+/// WAT source of a guest that answers `Request::Describe`, the only request serialized as a JSON
+/// string, with `describe_reply` and every other request with `reply`, whatever their input.
+pub fn canned_guest(describe_reply: &str, reply: &str) -> String {
+	let escape = |reply: &str| reply.replace('\\', "\\\\").replace('"', "\\\"");
+	format!(
+		r#"(module
+			(memory (export "memory") 1)
+			(global $next (mut i32) (i32.const 32768))
+			(data (i32.const 0) "{}")
+			(data (i32.const 16384) "{}")
+			(func (export "alloc") (param $size i32) (result i32)
+				(global.get $next)
+				(global.set $next (i32.add (global.get $next) (local.get $size))))
+			(func (export "dealloc") (param i32 i32))
+			(func (export "apply") (param $input i32) (param i32) (param $output i32) (param $size i32)
+				(if (i32.eq (i32.load8_u (local.get $input)) (i32.const 34))
+					(then
+						(i32.store (local.get $output) (i32.const 0))
+						(i32.store (local.get $size) (i32.const {})))
+					(else
+						(i32.store (local.get $output) (i32.const 16384))
+						(i32.store (local.get $size) (i32.const {}))))))"#,
+		escape(describe_reply),
+		escape(reply),
+		describe_reply.len(),
+		reply.len(),
+	)
+}
@@ -1,5 +1,11 @@
+#[cfg(test)]
+pub use canned_guest::canned_guest;
 pub use module_cache::ModuleCache;
 pub use program::Program;
+pub use schema_cache::SchemaCache;
 
+#[cfg(test)]
+mod canned_guest;
 mod module_cache;
 mod program;
+mod schema_cache;
@@ -1,11 +1,17 @@
 use std::collections::HashMap;
 use std::ffi::OsStr;
 
+use sha2::{Digest, Sha256};
 use walkdir::{DirEntry, WalkDir};
 use wasmtime::{Engine, Module};
 
 pub struct ModuleCache {
-	map: HashMap<String, Module>,
+	map: HashMap<String, CachedModule>,
+}
+
+struct CachedModule {
+	module: Module,
+	hash: String,
 }
 
 impl ModuleCache {
@@ -13,15 +19,33 @@ impl ModuleCache {
 		let mut map = HashMap::new();
 		for entry in WalkDir::new(directory).into_iter().filter_map(|e| e.ok()) {
 			if let Some((filename, path)) = extract_wasm_file_name(entry) {
-				let module = Module::from_file(engine, path)?;
-				map.insert(filename, module);
+				let bytes = std::fs::read(path)?;
+				let module = Module::new(engine, bytes.as_slice())?;
+				let hash = format!("{:x}", Sha256::digest(bytes.as_slice()));
+				map.insert(filename, CachedModule { module, hash });
 			}
 		}
 		Ok(ModuleCache { map })
 	}
 
+	/// A cache holding one module compiled from `source`, which may be WAT text, for tests.
+	#[cfg(test)]
+	pub fn with_module(engine: &Engine, id: &str, source: &str) -> anyhow::Result<ModuleCache> {
+		let module = Module::new(engine, source)?;
+		let hash = format!("{:x}", Sha256::digest(source.as_bytes()));
+		let mut map = HashMap::new();
+		map.insert(id.to_string(), CachedModule { module, hash });
+		Ok(ModuleCache { map })
+	}
+
 	pub fn get_module(&self, id: &str) -> Option<&Module> {
-		self.map.get(id)
+		self.map.get(id).map(|cached_module| &cached_module.module)
+	}
+
+	pub fn get_module_hash(&self, id: &str) -> Option<&str> {
+		self.map
+			.get(id)
+			.map(|cached_module| cached_module.hash.as_str())
 	}
 }
 
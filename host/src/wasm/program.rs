@@ -1,9 +1,9 @@
-use std::i32;
-
 use tokio::time::Instant;
 use wasmtime::{Engine, Instance, Memory, Module, Store, TypedFunc};
 
-use common::{Request, Response};
+use common::{Request, Response, Schemas, Snapshot};
+
+use crate::error::HostError;
 
 const MEMORY_EXPORT_NAME: &str = "memory";
 const ALLOC_FUNC_EXPORT_NAME: &str = "alloc";
@@ -20,8 +20,12 @@ pub struct Program {
 
 impl Program {
 	pub fn new(engine: &Engine, module: &Module) -> anyhow::Result<Program> {
-		let mut store = Store::new(&engine, ());
-		let instance = Instance::new(&mut store, &module, &[])?;
+		Ok(Program::instantiate(engine, module).map_err(HostError::trap)?)
+	}
+
+	fn instantiate(engine: &Engine, module: &Module) -> anyhow::Result<Program> {
+		let mut store = Store::new(engine, ());
+		let instance = Instance::new(&mut store, module, &[])?;
 		let memory = instance
 			.get_memory(&mut store, MEMORY_EXPORT_NAME)
 			.ok_or(anyhow::Error::msg("error_accessing_memory"))?;
@@ -119,12 +123,52 @@ impl Program {
 	}
 
 	pub fn execute_request(&mut self, request: &Request) -> anyhow::Result<Response> {
+		let response_string = self.execute_raw(request)?;
+		let response = serde_json::from_str(response_string.as_str())
+			.map_err(|error| HostError::trap(error.into()))?;
+		Ok(response)
+	}
+
+	fn execute_raw(&mut self, request: &Request) -> anyhow::Result<String> {
 		let now = Instant::now();
 		let request_string = serde_json::to_string(request)?;
-		let response_string = self.apply(request_string.as_str())?;
-		let response = serde_json::from_str(response_string.as_str())?;
+		let response_string = self
+			.apply(request_string.as_str())
+			.map_err(HostError::trap)?;
 		let elapsed = now.elapsed();
 		println!("execution_duration: {:.2?}", elapsed);
-		Ok(response)
+		Ok(response_string)
+	}
+
+	/// Runs an initialization or event request and returns the resulting snapshot.
+	pub fn execute_snapshot(&mut self, request: &Request) -> anyhow::Result<Snapshot> {
+		match self.execute_request(request)? {
+			Response::Snapshot(snapshot) => Ok(snapshot),
+			Response::Error(error) => Err(HostError::from_guest(error).into()),
+			_ => Err(HostError::Trap("unexpected_response".to_string()).into()),
+		}
+	}
+
+	/// Asks the module for its schemas, or `None` if it does not know `Request::Describe`.
+	/// Modules built before it existed reply with the parse error as plain text, or as an
+	/// `invalid_request` error once they report parse errors as responses.
+	pub fn describe(&mut self) -> anyhow::Result<Option<Schemas>> {
+		let response_string = self.execute_raw(&Request::Describe)?;
+		match serde_json::from_str(response_string.as_str()) {
+			Ok(Response::Schemas(schemas)) => Ok(Some(schemas)),
+			Ok(Response::Error(error)) if error.code == "invalid_request" => Ok(None),
+			Ok(Response::Error(error)) => Err(HostError::from_guest(error).into()),
+			Ok(_) => Err(HostError::Trap("unexpected_response".to_string()).into()),
+			Err(_) => Ok(None),
+		}
+	}
+
+	/// Asks the guest to answer `query` from `state` and returns the JSON result.
+	pub fn query(&mut self, state: String, query: String) -> anyhow::Result<String> {
+		match self.execute_request(&Request::Query { state, query })? {
+			Response::QueryResult(result) => Ok(result),
+			Response::Error(error) => Err(HostError::from_guest(error).into()),
+			_ => Err(HostError::Trap("unexpected_response".to_string()).into()),
+		}
 	}
 }
@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use jsonschema::JSONSchema;
+use serde_json::Value;
+use wasmtime::{Engine, Module};
+
+use common::Schemas;
+
+use crate::error::{HostError, Violation};
+use crate::wasm::Program;
+
+/// Schemas a module describes itself with, and its parameter and event schemas compiled.
+pub struct ModuleSchemas {
+	described: Schemas,
+	parameter: JSONSchema,
+	event: JSONSchema,
+}
+
+impl ModuleSchemas {
+	pub fn described(&self) -> &Schemas {
+		&self.described
+	}
+
+	pub fn validate_parameter(&self, parameter: &Value) -> Result<(), HostError> {
+		validate(&self.parameter, parameter)
+	}
+
+	pub fn validate_event(&self, event: &Value) -> Result<(), HostError> {
+		validate(&self.event, event)
+	}
+}
+
+/// Schemas of every module, asked from the module the first time they are needed. Modules that
+/// cannot describe themselves, such as ones built before `Request::Describe` existed, or that
+/// describe themselves with schemas that do not compile, are not validated. A trap or a failure
+/// of the module to describe itself is returned and asked again next time.
+#[derive(Default)]
+pub struct SchemaCache {
+	map: Mutex<HashMap<String, Option<Arc<ModuleSchemas>>>>,
+}
+
+impl SchemaCache {
+	pub fn new() -> SchemaCache {
+		SchemaCache::default()
+	}
+
+	pub fn get(
+		&self,
+		engine: &Engine,
+		wasm: &str,
+		module: &Module,
+	) -> anyhow::Result<Option<Arc<ModuleSchemas>>> {
+		if let Some(schemas) = self.map.lock().unwrap().get(wasm) {
+			return Ok(schemas.clone());
+		}
+		let schemas = match Program::new(engine, module)?.describe()? {
+			Some(schemas) => compile_all(wasm, schemas),
+			None => {
+				println!("describe_unsupported: {}", wasm);
+				None
+			}
+		};
+		self.map
+			.lock()
+			.unwrap()
+			.insert(wasm.to_string(), schemas.clone());
+		Ok(schemas)
+	}
+}
+
+fn compile_all(wasm: &str, described: Schemas) -> Option<Arc<ModuleSchemas>> {
+	let compiled = compile(described.parameter.as_str())
+		.and_then(|parameter| Ok((parameter, compile(described.event.as_str())?)));
+	match compiled {
+		Ok((parameter, event)) => Some(Arc::new(ModuleSchemas {
+			described,
+			parameter,
+			event,
+		})),
+		Err(error) => {
+			println!("invalid_schema: {}: {}", wasm, error);
+			None
+		}
+	}
+}
+
+fn compile(schema: &str) -> anyhow::Result<JSONSchema> {
+	let schema = serde_json::from_str(schema).map_err(|error| HostError::trap(error.into()))?;
+	JSONSchema::compile(&schema)
+		.map_err(|error| HostError::Trap(format!("invalid_schema: {}", error)).into())
+}
+
+fn validate(schema: &JSONSchema, instance: &Value) -> Result<(), HostError> {
+	schema.validate(instance).map_err(|errors| {
+		HostError::SchemaViolation(
+			errors
+				.map(|error| Violation {
+					pointer: error.instance_path.to_string(),
+					message: error.to_string(),
+				})
+				.collect(),
+		)
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use common::{GuestError, Response};
+	use serde_json::json;
+
+	use super::*;
+	use crate::wasm::canned_guest;
+
+	#[test]
+	fn test_validate() {
+		let schema = json!({
+			"type": "object",
+			"required": ["amount"],
+			"properties": {
+				"amount": { "type": "integer", "minimum": 0 },
+				"items": { "type": "array", "items": { "type": "string" } }
+			}
+		});
+		let schemas = compile_all(
+			"a.wasm",
+			Schemas {
+				parameter: schema.to_string(),
+				event: "true".to_string(),
+				state: "true".to_string(),
+				query: "true".to_string(),
+			},
+		)
+		.unwrap();
+		assert!(schemas.validate_parameter(&json!({ "amount": 3 })).is_ok());
+		assert!(schemas.validate_event(&json!({ "anything": 1 })).is_ok());
+
+		let error = schemas
+			.validate_parameter(&json!({ "amount": -1, "items": ["a", 2] }))
+			.unwrap_err();
+		let HostError::SchemaViolation(violations) = &error else {
+			panic!("expected schema violations");
+		};
+		let mut pointers: Vec<&str> = violations
+			.iter()
+			.map(|violation| violation.pointer.as_str())
+			.collect();
+		pointers.sort();
+		assert_eq!(pointers, vec!["/amount", "/items/1"]);
+		assert_eq!(error.status_code(), axum::http::StatusCode::BAD_REQUEST);
+
+		let error = schemas.validate_parameter(&json!({})).unwrap_err();
+		assert_eq!(error.code(), "schema_violation");
+		assert_eq!(error.details().unwrap()[0]["pointer"], "");
+	}
+
+	#[test]
+	fn test_describe() {
+		let engine = Engine::default();
+		let get = |describe_reply: &str| {
+			let module = Module::new(&engine, canned_guest(describe_reply, "")).unwrap();
+			let cache = SchemaCache::new();
+			let schemas = cache.get(&engine, "a.wasm", &module);
+			let cached = cache.map.lock().unwrap().contains_key("a.wasm");
+			(schemas, cached)
+		};
+
+		let (schemas, cached) = get("unknown variant `Describe`, expected one of `Event`");
+		assert!(schemas.unwrap().is_none());
+		assert!(cached);
+
+		let failed = Response::Error(GuestError::executor("describe_failed", "no schema"));
+		let (schemas, cached) = get(serde_json::to_string(&failed).unwrap().as_str());
+		let error = schemas.err().unwrap();
+		assert!(matches!(
+			error.downcast_ref::<HostError>(),
+			Some(HostError::Guest(guest_error)) if guest_error.code == "describe_failed"
+		));
+		assert!(!cached);
+
+		let described = Response::Schemas(Schemas {
+			parameter: "true".to_string(),
+			event: json!({ "type": "integer" }).to_string(),
+			state: "true".to_string(),
+			query: "true".to_string(),
+		});
+		let (schemas, cached) = get(serde_json::to_string(&described).unwrap().as_str());
+		let schemas = schemas.unwrap().unwrap();
+		assert!(schemas.validate_event(&json!(1)).is_ok());
+		assert!(schemas.validate_event(&json!("x")).is_err());
+		assert!(cached);
+	}
+
+	#[test]
+	fn test_unusable_schemas() {
+		let schemas = Schemas {
+			parameter: json!({ "type": "nonsense" }).to_string(),
+			event: "true".to_string(),
+			state: "true".to_string(),
+			query: "true".to_string(),
+		};
+		assert!(compile_all("a.wasm", schemas).is_none());
+	}
+}